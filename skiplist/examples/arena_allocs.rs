@@ -0,0 +1,88 @@
+// Compares the allocations and timings of `SkipList` and `ArenaSkipList`
+// for 1M random entries.
+//
+//     cargo run --release -p skiplist --example arena_allocs
+//
+// Cache misses are not measured here. On Linux, compare them by running
+// the example under `perf stat -e cache-misses`.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use skiplist::{ArenaSkipList, SkipList};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+struct Counting;
+
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const N: usize = 1_000_000;
+
+fn measure<T, F: FnOnce() -> T>(name: &str, f: F) -> T {
+    let allocs = ALLOCS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let result = f();
+    println!(
+        "{:<28} {:>10} allocations {:>10.1?}",
+        name,
+        ALLOCS.load(Ordering::Relaxed) - allocs,
+        start.elapsed()
+    );
+    result
+}
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let keys: Vec<u64> = (0..N).map(|_| rng.gen()).collect();
+
+    let sk = measure("SkipList insert", || {
+        let mut sk = SkipList::new();
+        for k in &keys {
+            sk.insert(*k, *k);
+        }
+        sk
+    });
+    let found = measure("SkipList get", || keys.iter().filter(|k| sk.get(*k).is_some()).count());
+    assert_eq!(found, N);
+    measure("SkipList drop", || drop(sk));
+
+    let sk = measure("ArenaSkipList insert", || {
+        let mut sk = ArenaSkipList::new();
+        for k in &keys {
+            sk.insert(*k, *k);
+        }
+        sk
+    });
+    let found = measure("ArenaSkipList get", || keys.iter().filter(|k| sk.get(*k).is_some()).count());
+    assert_eq!(found, N);
+    measure("ArenaSkipList drop", || drop(sk));
+
+    let sk = measure("ArenaSkipList with_capacity", || {
+        let mut sk = ArenaSkipList::with_capacity(N);
+        for k in &keys {
+            sk.insert(*k, *k);
+        }
+        sk
+    });
+    drop(sk);
+}
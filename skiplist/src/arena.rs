@@ -0,0 +1,704 @@
+use crate::comparator::{Comparator, OrdComparator};
+use crate::level::GeometricalLevelGenerator;
+use crate::level::{LevelGenerator, DEFAULT_MAX_LEVEL};
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::iter::FromIterator;
+use core::iter::FusedIterator;
+use core::ops::{Bound, RangeBounds};
+
+// Null link, also the head when standing for a pre node.
+const NIL: u32 = u32::MAX;
+
+// Towers higher than this are cut, so the pre nodes of a search fit in an
+// array on the stack.
+const MAX_HEIGHT: usize = DEFAULT_MAX_LEVEL;
+
+struct Node<K, V> {
+    key: K,
+    value: V,
+    // Back link of level 0, `NIL` for the first node.
+    prev: u32,
+    // The tower is `links[tower..tower + height]`.
+    tower: u32,
+    height: u32,
+}
+
+enum Slot<K, V> {
+    Occupied(Node<K, V>),
+    // Next free slot, `NIL` at the end of the free list.
+    Vacant(u32),
+}
+
+/// An ordered map based on a skip list whose nodes live in a slab.
+///
+/// Nodes are stored in one `Vec` and link to each other by index, and the
+/// towers of all nodes are packed back to back in another `Vec`. Inserting
+/// an entry allocates nothing once the storage has grown large enough, and
+/// removed nodes and towers are reused by later insertions. Compared to
+/// `SkipList` it gives up positional access and the `O(log n)` split and
+/// concatenation.
+///
+/// ```
+/// use skiplist::ArenaSkipList;
+///
+/// let mut sk = ArenaSkipList::with_capacity(3);
+/// sk.insert(2, "b");
+/// sk.insert(1, "a");
+/// sk.insert(3, "c");
+/// assert_eq!(sk.remove(&2), Some("b"));
+/// assert!(sk.iter().eq(vec![(&1, &"a"), (&3, &"c")]));
+/// ```
+pub struct ArenaSkipList<K, V, C = OrdComparator> {
+    slots: Vec<Slot<K, V>>,
+    // Head of the free list of `slots`.
+    free: u32,
+    links: Vec<u32>,
+    // Released towers by height - 1, chained through their first link.
+    free_towers: Vec<u32>,
+    head: Vec<u32>,
+    tail: u32,
+    len: usize,
+    level_generator: Box<dyn LevelGenerator>,
+    comparator: C,
+}
+
+impl<K, V> ArenaSkipList<K, V> {
+    /// Creates an empty list with the default `GeometricalLevelGenerator`,
+    /// which is seeded from the thread RNG, or with a fixed seed without
+    /// `std`.
+    pub fn new() -> ArenaSkipList<K, V> {
+        ArenaSkipList::with_comparator(OrdComparator)
+    }
+
+    /// Creates an empty list with room for `capacity` entries before the
+    /// storage grows.
+    pub fn with_capacity(capacity: usize) -> ArenaSkipList<K, V> {
+        let mut list = ArenaSkipList::new();
+        list.reserve(capacity);
+        list
+    }
+
+    /// Creates an empty list whose tower heights are chosen by
+    /// `level_generator`.
+    pub fn with_level_generator<G>(level_generator: G) -> ArenaSkipList<K, V>
+    where
+        G: LevelGenerator + 'static,
+    {
+        ArenaSkipList::with_comparator_and_level_generator(OrdComparator, level_generator)
+    }
+}
+
+impl<K, V, C> ArenaSkipList<K, V, C> {
+    /// Creates an empty list whose keys are ordered by `comparator`.
+    pub fn with_comparator(comparator: C) -> ArenaSkipList<K, V, C> {
+        ArenaSkipList::with_comparator_and_level_generator(
+            comparator,
+            GeometricalLevelGenerator::default(),
+        )
+    }
+
+    /// Creates an empty list whose keys are ordered by `comparator` and
+    /// whose tower heights are chosen by `level_generator`.
+    pub fn with_comparator_and_level_generator<G>(
+        comparator: C,
+        level_generator: G,
+    ) -> ArenaSkipList<K, V, C>
+    where
+        G: LevelGenerator + 'static,
+    {
+        ArenaSkipList {
+            slots: vec![],
+            free: NIL,
+            links: vec![],
+            free_towers: vec![],
+            head: vec![],
+            tail: NIL,
+            len: 0,
+            level_generator: Box::new(level_generator),
+            comparator,
+        }
+    }
+
+    /// Reserves room for at least `additional` more entries.
+    pub fn reserve(&mut self, additional: usize) {
+        self.slots.reserve(additional);
+        // Towers have 2 levels on average with the default generator.
+        self.links.reserve(additional * 2);
+    }
+
+    /// Returns the number of elements in the list.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the list contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Clears the list, removing all elements. The storage is kept for
+    /// later insertions.
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.free = NIL;
+        self.links.clear();
+        self.free_towers.clear();
+        self.head.clear();
+        self.tail = NIL;
+        self.len = 0;
+    }
+
+    /// Returns the first key-value pair in the list.
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        let first = self.head.first().copied().unwrap_or(NIL);
+        self._entry(first)
+    }
+
+    /// Returns the last key-value pair in the list.
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        self._entry(self.tail)
+    }
+
+    /// Gets a double-ended iterator over the entries of the list, sorted by
+    /// key.
+    pub fn iter(&self) -> ArenaIter<'_, K, V> {
+        let first = self.head.first().copied().unwrap_or(NIL);
+        ArenaIter {
+            range: ArenaRange::new(&self.slots, &self.links, first, self.tail),
+            len: self.len,
+        }
+    }
+
+    fn _entry(&self, index: u32) -> Option<(&K, &V)> {
+        if index == NIL {
+            return None;
+        }
+        let node = self._node(index);
+        Some((&node.key, &node.value))
+    }
+
+    fn _node(&self, index: u32) -> &Node<K, V> {
+        _node(&self.slots, index)
+    }
+
+    fn _node_mut(&mut self, index: u32) -> &mut Node<K, V> {
+        match &mut self.slots[index as usize] {
+            Slot::Occupied(node) => node,
+            Slot::Vacant(_) => unreachable!("link to a vacant slot"),
+        }
+    }
+
+    // The node after `pre` on `level`, a `NIL` pre stands for the head.
+    fn _next(&self, pre: u32, level: usize) -> u32 {
+        if pre == NIL {
+            self.head.get(level).copied().unwrap_or(NIL)
+        } else {
+            self.links[self._node(pre).tower as usize + level]
+        }
+    }
+
+    fn _set_next(&mut self, pre: u32, level: usize, next: u32) {
+        if pre == NIL {
+            self.head[level] = next;
+        } else {
+            let tower = self._node(pre).tower as usize;
+            self.links[tower + level] = next;
+        }
+    }
+
+    // Take a free tower of `height` levels, or grow `links`.
+    fn _alloc_tower(&mut self, height: usize) -> u32 {
+        match self.free_towers.get(height - 1) {
+            Some(&tower) if tower != NIL => {
+                self.free_towers[height - 1] = self.links[tower as usize];
+                tower
+            }
+            _ => {
+                let tower = self.links.len();
+                assert!(tower + height < NIL as usize, "too many levels in ArenaSkipList");
+                self.links.resize(tower + height, NIL);
+                tower as u32
+            }
+        }
+    }
+
+    fn _free_tower(&mut self, tower: u32, height: usize) {
+        if self.free_towers.len() < height {
+            self.free_towers.resize(height, NIL);
+        }
+        self.links[tower as usize] = self.free_towers[height - 1];
+        self.free_towers[height - 1] = tower;
+    }
+
+    // Take a free slot for `node`, or grow `slots`.
+    fn _alloc_slot(&mut self, node: Node<K, V>) -> u32 {
+        if self.free == NIL {
+            let index = self.slots.len();
+            assert!(index < NIL as usize, "too many entries in ArenaSkipList");
+            self.slots.push(Slot::Occupied(node));
+            return index as u32;
+        }
+
+        let index = self.free;
+        match core::mem::replace(&mut self.slots[index as usize], Slot::Occupied(node)) {
+            Slot::Vacant(next) => self.free = next,
+            Slot::Occupied(_) => unreachable!("free list points to an occupied slot"),
+        }
+        index
+    }
+}
+
+impl<K, V, C: Comparator<K>> ArenaSkipList<K, V, C> {
+    /// Inserts a key-value pair into the list, returning the old value if
+    /// the key was already in the list. The key itself is not updated.
+    pub fn insert(&mut self, k: K, v: V) -> Option<V> {
+        let pres = self._get_pre_nodes(&k);
+        let current = self._next(pres[0], 0);
+        if current != NIL {
+            let comparator = &self.comparator;
+            let node = match &mut self.slots[current as usize] {
+                Slot::Occupied(node) => node,
+                Slot::Vacant(_) => unreachable!("link to a vacant slot"),
+            };
+            if comparator.compare(&node.key, &k) == Ordering::Equal {
+                return Some(core::mem::replace(&mut node.value, v));
+            }
+        }
+
+        // The list grows one level at most for every insertion.
+        let height = (self.level_generator.random() + 1)
+            .min(self.head.len() + 1)
+            .min(MAX_HEIGHT);
+        if height > self.head.len() {
+            self.head.push(NIL);
+        }
+        let tower = self._alloc_tower(height);
+        let index = self._alloc_slot(Node {
+            key: k,
+            value: v,
+            prev: pres[0],
+            tower,
+            height: height as u32,
+        });
+        for (l, pre) in pres.iter().enumerate().take(height) {
+            self.links[tower as usize + l] = self._next(*pre, l);
+            self._set_next(*pre, l, index);
+        }
+
+        let next = self.links[tower as usize];
+        if next == NIL {
+            self.tail = index;
+        } else {
+            self._node_mut(next).prev = index;
+        }
+        self.len += 1;
+        None
+    }
+
+    /// Removes a key from the list, returning the value at the key if the
+    /// key was previously in the list.
+    pub fn remove<Q>(&mut self, q: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        self.remove_entry(q).map(|(_, v)| v)
+    }
+
+    /// Removes a key from the list, returning the stored key and value if
+    /// the key was previously in the list.
+    pub fn remove_entry<Q>(&mut self, q: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let pres = self._get_pre_nodes(q);
+        let current = self._next(pres[0], 0);
+        if current == NIL
+            || self.comparator.compare(q, self._node(current).key.borrow()) != Ordering::Equal
+        {
+            return None;
+        }
+
+        let (prev, tower, height) = {
+            let node = self._node(current);
+            (node.prev, node.tower, node.height as usize)
+        };
+        for (l, pre) in pres.iter().enumerate().take(height) {
+            let next = self.links[tower as usize + l];
+            self._set_next(*pre, l, next);
+        }
+        let next = self.links[tower as usize];
+        if next == NIL {
+            self.tail = prev;
+        } else {
+            self._node_mut(next).prev = prev;
+        }
+        while let Some(&NIL) = self.head.last() {
+            self.head.pop();
+        }
+
+        self._free_tower(tower, height);
+        let slot = core::mem::replace(&mut self.slots[current as usize], Slot::Vacant(self.free));
+        self.free = current;
+        self.len -= 1;
+        match slot {
+            Slot::Occupied(node) => Some((node.key, node.value)),
+            Slot::Vacant(_) => unreachable!("link to a vacant slot"),
+        }
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, q: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let index = self._get_node(q);
+        if index == NIL {
+            return None;
+        }
+        Some(&self._node(index).value)
+    }
+
+    /// Returns a mutable reference to the value corresponding to the key.
+    pub fn get_mut<Q>(&mut self, q: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let index = self._get_node(q);
+        if index == NIL {
+            return None;
+        }
+        Some(&mut self._node_mut(index).value)
+    }
+
+    /// Returns `true` if the list contains a value for the specified key.
+    pub fn contains_key<Q>(&self, q: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        self._get_node(q) != NIL
+    }
+
+    /// Constructs a double-ended iterator over a sub-range of entries in
+    /// the list.
+    ///
+    /// Panics if range `start > end` or if range `start == end` and both
+    /// bounds are `Excluded`.
+    pub fn range<Q, R>(&self, range: R) -> ArenaRange<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+        R: RangeBounds<Q>,
+    {
+        crate::skip::_check_range(&range, &self.comparator);
+
+        let front = match range.start_bound() {
+            Bound::Unbounded => self._next(NIL, 0),
+            Bound::Included(key) => self._next(self._get_pre_node(key, false), 0),
+            Bound::Excluded(key) => self._next(self._get_pre_node(key, true), 0),
+        };
+        let back = match range.end_bound() {
+            Bound::Unbounded => self.tail,
+            Bound::Included(key) => self._get_pre_node(key, true),
+            Bound::Excluded(key) => self._get_pre_node(key, false),
+        };
+
+        let empty = front == NIL
+            || back == NIL
+            || self.comparator.compare(
+                self._node(front).key.borrow(),
+                self._node(back).key.borrow(),
+            ) == Ordering::Greater;
+        if empty {
+            ArenaRange::new(&self.slots, &self.links, NIL, NIL)
+        } else {
+            ArenaRange::new(&self.slots, &self.links, front, back)
+        }
+    }
+
+    fn _get_node<Q>(&self, q: &Q) -> u32
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let next = self._next(self._get_pre_node(q, false), 0);
+        if next != NIL && self.comparator.compare(q, self._node(next).key.borrow()) == Ordering::Equal
+        {
+            next
+        } else {
+            NIL
+        }
+    }
+
+    // Last node whose key is less than `q`, or not greater than `q` if
+    // `inclusive`. `NIL` stands for the head.
+    fn _get_pre_node<Q>(&self, q: &Q, inclusive: bool) -> u32
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let mut pre = NIL;
+        for l in (0..self.head.len()).rev() {
+            loop {
+                let next = self._next(pre, l);
+                if next == NIL {
+                    break;
+                }
+                match self.comparator.compare(q, self._node(next).key.borrow()) {
+                    Ordering::Greater => (),
+                    Ordering::Equal if inclusive => (),
+                    Ordering::Equal | Ordering::Less => break,
+                }
+                pre = next;
+            }
+        }
+        pre
+    }
+
+    // Same as `_get_pre_node` without `inclusive`, but records the pre node
+    // of every level. Levels above the list are the head.
+    fn _get_pre_nodes<Q>(&self, q: &Q) -> [u32; MAX_HEIGHT]
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let mut pres = [NIL; MAX_HEIGHT];
+        let mut pre = NIL;
+        for l in (0..self.head.len()).rev() {
+            loop {
+                let next = self._next(pre, l);
+                if next == NIL
+                    || self.comparator.compare(q, self._node(next).key.borrow()) != Ordering::Greater
+                {
+                    break;
+                }
+                pre = next;
+            }
+            pres[l] = pre;
+        }
+        pres
+    }
+}
+
+impl<K, V> Default for ArenaSkipList<K, V> {
+    fn default() -> ArenaSkipList<K, V> {
+        ArenaSkipList::new()
+    }
+}
+
+impl<K: Ord, V> FromIterator<(K, V)> for ArenaSkipList<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> ArenaSkipList<K, V> {
+        let iter = iter.into_iter();
+        let mut list = ArenaSkipList::with_capacity(iter.size_hint().0);
+        list.extend(iter);
+        list
+    }
+}
+
+impl<K, V, C: Comparator<K>> Extend<(K, V)> for ArenaSkipList<K, V, C> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+fn _node<K, V>(slots: &[Slot<K, V>], index: u32) -> &Node<K, V> {
+    match &slots[index as usize] {
+        Slot::Occupied(node) => node,
+        Slot::Vacant(_) => unreachable!("link to a vacant slot"),
+    }
+}
+
+/// An iterator over a sub-range of entries in an `ArenaSkipList`.
+pub struct ArenaRange<'a, K, V> {
+    slots: &'a [Slot<K, V>],
+    links: &'a [u32],
+    // Both ends are inclusive, `NIL` once the range is exhausted.
+    front: u32,
+    back: u32,
+}
+
+impl<'a, K, V> ArenaRange<'a, K, V> {
+    fn new(slots: &'a [Slot<K, V>], links: &'a [u32], front: u32, back: u32) -> ArenaRange<'a, K, V> {
+        ArenaRange { slots, links, front, back }
+    }
+
+    // Take `index` out of the range, `next` is the node following it
+    // towards the other end.
+    fn _advance(&mut self, index: u32, next: u32) -> (&'a K, &'a V) {
+        if self.front == self.back {
+            self.front = NIL;
+            self.back = NIL;
+        } else if index == self.front {
+            self.front = next;
+        } else {
+            self.back = next;
+        }
+        let node = _node(self.slots, index);
+        (&node.key, &node.value)
+    }
+}
+
+impl<'a, K, V> Iterator for ArenaRange<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front == NIL {
+            return None;
+        }
+        let front = self.front;
+        let next = self.links[_node(self.slots, front).tower as usize];
+        Some(self._advance(front, next))
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for ArenaRange<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.back == NIL {
+            return None;
+        }
+        let back = self.back;
+        let prev = _node(self.slots, back).prev;
+        Some(self._advance(back, prev))
+    }
+}
+
+impl<'a, K, V> FusedIterator for ArenaRange<'a, K, V> {}
+
+/// An iterator over the entries of an `ArenaSkipList`.
+pub struct ArenaIter<'a, K, V> {
+    range: ArenaRange<'a, K, V>,
+    len: usize,
+}
+
+impl<'a, K, V> Iterator for ArenaIter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.range.next()?;
+        self.len -= 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for ArenaIter<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let item = self.range.next_back()?;
+        self.len -= 1;
+        Some(item)
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for ArenaIter<'a, K, V> {}
+
+impl<'a, K, V> FusedIterator for ArenaIter<'a, K, V> {}
+
+impl<'a, K, V, C> IntoIterator for &'a ArenaSkipList<K, V, C> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = ArenaIter<'a, K, V>;
+
+    fn into_iter(self) -> ArenaIter<'a, K, V> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comparator::ReverseComparator;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn same_as_btreemap() {
+        let mut rng = StdRng::seed_from_u64(13);
+        let mut sk = ArenaSkipList::with_level_generator(GeometricalLevelGenerator::seeded(13));
+        let mut bt = BTreeMap::new();
+        for _ in 0..5000 {
+            let k: u32 = rng.gen_range(0, 1000);
+            match rng.gen_range(0, 4) {
+                0 => assert_eq!(sk.remove(&k), bt.remove(&k)),
+                1 => assert_eq!(sk.get(&k), bt.get(&k)),
+                _ => assert_eq!(sk.insert(k, k * 2), bt.insert(k, k * 2)),
+            }
+            assert_eq!(sk.len(), bt.len());
+        }
+        assert!(sk.iter().eq(bt.iter()));
+        assert!(sk.iter().rev().eq(bt.iter().rev()));
+        assert_eq!(sk.first_key_value(), bt.iter().next());
+        assert_eq!(sk.last_key_value(), bt.iter().next_back());
+        for &(s, e) in &[(0, 1000), (100, 200), (150, 151), (500, 500), (999, 1000)] {
+            assert!(sk.range(s..e).eq(bt.range(s..e)));
+            assert!(sk.range(s..=e).rev().eq(bt.range(s..=e).rev()));
+            assert!(sk.range((Bound::Excluded(s), Bound::Unbounded)).eq(bt.range(s + 1..)));
+        }
+        let mut range = sk.range(100..200);
+        let mut bt_range = bt.range(100..200);
+        while let Some(item) = range.next() {
+            assert_eq!(Some(item), bt_range.next());
+            assert_eq!(range.next_back(), bt_range.next_back());
+        }
+        assert_eq!(bt_range.next(), None);
+    }
+
+    #[test]
+    fn reuse_storage() {
+        let mut sk = ArenaSkipList::with_capacity(100);
+        for i in 0..100 {
+            sk.insert(i, i);
+        }
+        let (slots, links) = (sk.slots.len(), sk.links.len());
+        for round in 0..10 {
+            for i in 0..100 {
+                assert_eq!(sk.remove(&i), Some(i + round * 100));
+            }
+            assert!(sk.is_empty());
+            for i in 0..100 {
+                sk.insert(i, i + (round + 1) * 100);
+            }
+        }
+        assert_eq!(sk.slots.len(), slots);
+        // Towers are reused by height, new heights may need a few more.
+        assert!(sk.links.len() < links * 2);
+        *sk.get_mut(&5).unwrap() = 0;
+        assert_eq!(sk.get(&5), Some(&0));
+
+        sk.clear();
+        assert_eq!(sk.iter().next(), None);
+        sk.insert(1, 1);
+        assert!(sk.iter().eq(vec![(&1, &1)]));
+    }
+
+    #[test]
+    fn comparator() {
+        let mut sk = ArenaSkipList::with_comparator(ReverseComparator(OrdComparator));
+        sk.extend((0..100).map(|i| (i, i)));
+        assert!(sk.iter().map(|(k, _)| *k).eq((0..100).rev()));
+        assert_eq!(sk.remove(&50), Some(50));
+        let range = (Bound::Included(60), Bound::Excluded(40));
+        assert!(sk.range(range).map(|(k, _)| *k).eq((41..=60).rev().filter(|k| *k != 50)));
+    }
+}
@@ -0,0 +1,62 @@
+use core::cmp::Ordering;
+
+/// Decides the order of keys in a `SkipList`.
+///
+/// Closures and functions taking two references are comparators too:
+///
+/// ```
+/// use skiplist::SkipList;
+///
+/// let mut sk = SkipList::with_comparator(|a: &i32, b: &i32| b.cmp(a));
+/// sk.insert(1, "a");
+/// sk.insert(2, "b");
+/// assert_eq!(sk.first_key_value(), Some((&2, &"b")));
+/// ```
+pub trait Comparator<T: ?Sized> {
+    fn compare(&self, a: &T, b: &T) -> Ordering;
+}
+
+/// Orders keys by their `Ord` implementation, the default comparator.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct OrdComparator;
+
+impl<T: Ord + ?Sized> Comparator<T> for OrdComparator {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+/// Reverses the order of another comparator.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ReverseComparator<C = OrdComparator>(pub C);
+
+impl<T: ?Sized, C: Comparator<T>> Comparator<T> for ReverseComparator<C> {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self.0.compare(b, a)
+    }
+}
+
+impl<T: ?Sized, F> Comparator<T> for F
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self(a, b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comparators() {
+        assert_eq!(OrdComparator.compare(&1, &2), Ordering::Less);
+        assert_eq!(OrdComparator.compare("b", "a"), Ordering::Greater);
+        assert_eq!(ReverseComparator(OrdComparator).compare(&1, &2), Ordering::Greater);
+
+        let len = |a: &&str, b: &&str| a.len().cmp(&b.len());
+        assert_eq!(len.compare(&"aaa", &"b"), Ordering::Greater);
+        assert_eq!(ReverseComparator(len).compare(&"aaa", &"b"), Ordering::Less);
+    }
+}
@@ -0,0 +1,670 @@
+use crate::comparator::OrdComparator;
+use crate::level::DEFAULT_MAX_LEVEL;
+use crate::skip::_check_range;
+use crossbeam_epoch::{self as epoch, Atomic, Guard, Owned, Shared};
+use rand::Rng;
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds, RangeFull};
+use std::sync::atomic::{self, AtomicUsize, Ordering as MemOrdering};
+
+// An entry is removed in two steps. Taking its value out (leaving null) is
+// the moment it leaves the map, then every link of its tower gets tagged
+// with 1, so nothing can be linked after it anymore and searches unlink it.
+struct Node<K, V> {
+    key: K,
+    value: Atomic<V>,
+    // Number of levels the node is linked on, plus one while its tower is
+    // being built. The node is destroyed when it drops to 0.
+    refs: AtomicUsize,
+    tower: Box<[Atomic<Node<K, V>>]>,
+}
+
+impl<K, V> Node<K, V> {
+    fn _is_removed(&self, guard: &Guard) -> bool {
+        self.tower[0].load(MemOrdering::SeqCst, guard).tag() == 1
+    }
+
+    // Tag every link of the tower, top down.
+    fn _mark_tower(&self, guard: &Guard) {
+        for level in (0..self.tower.len()).rev() {
+            self.tower[level].fetch_or(1, MemOrdering::SeqCst, guard);
+        }
+    }
+
+    // Drop one reference, destroying the node when it was the last one.
+    //
+    // The node must not be reachable from any level once all its
+    // references are gone.
+    unsafe fn _decrement(&self, guard: &Guard) {
+        if self.refs.fetch_sub(1, MemOrdering::Release) == 1 {
+            atomic::fence(MemOrdering::Acquire);
+            guard.defer_destroy(Shared::from(self as *const Node<K, V>));
+        }
+    }
+}
+
+impl<K, V> Drop for Node<K, V> {
+    fn drop(&mut self) {
+        let guard = unsafe { epoch::unprotected() };
+        let value = self.value.load(MemOrdering::Relaxed, guard);
+        if !value.is_null() {
+            drop(unsafe { value.into_owned() });
+        }
+    }
+}
+
+// Result of a search: the node holding the key if any, and the links
+// around the key on every level.
+struct Position<'g, K, V> {
+    found: Option<&'g Node<K, V>>,
+    left: [&'g Atomic<Node<K, V>>; DEFAULT_MAX_LEVEL],
+    right: [Shared<'g, Node<K, V>>; DEFAULT_MAX_LEVEL],
+}
+
+/// An ordered map based on a lock-free skip list, which can be shared and
+/// modified by many threads at the same time.
+///
+/// Memory of removed entries is reclaimed with epoch based reclamation from
+/// `crossbeam-epoch`. Since an entry can be removed by another thread at any
+/// time, keys and values are cloned out of the list instead of borrowed.
+///
+/// ```
+/// use skiplist::ConcurrentSkipList;
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// let sk = Arc::new(ConcurrentSkipList::new());
+/// let handles: Vec<_> = (0..4)
+///     .map(|t| {
+///         let sk = sk.clone();
+///         thread::spawn(move || {
+///             for i in 0..100 {
+///                 sk.insert(i * 4 + t, t);
+///             }
+///         })
+///     })
+///     .collect();
+/// for handle in handles {
+///     handle.join().unwrap();
+/// }
+/// assert_eq!(sk.len(), 400);
+/// assert_eq!(sk.get(&6), Some(2));
+/// ```
+pub struct ConcurrentSkipList<K, V> {
+    head: Box<[Atomic<Node<K, V>>]>,
+    // Highest tower ever built, searches start from this level.
+    height: AtomicUsize,
+    len: AtomicUsize,
+}
+
+impl<K, V> ConcurrentSkipList<K, V> {
+    pub fn new() -> ConcurrentSkipList<K, V> {
+        ConcurrentSkipList {
+            head: (0..DEFAULT_MAX_LEVEL).map(|_| Atomic::null()).collect(),
+            height: AtomicUsize::new(1),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of elements in the list.
+    ///
+    /// The result is only a snapshot when other threads modify the list.
+    pub fn len(&self) -> usize {
+        self.len.load(MemOrdering::SeqCst)
+    }
+
+    /// Returns `true` if the list contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Towers are as high as the number of trailing ones of a random number
+    // plus one, which is a geometrical distribution with `p = 1/2`.
+    fn _random_height() -> usize {
+        let bits: u32 = rand::thread_rng().gen();
+        (bits.trailing_ones() as usize + 1).min(DEFAULT_MAX_LEVEL)
+    }
+}
+
+impl<K, V> ConcurrentSkipList<K, V>
+where
+    K: Ord + Send + 'static,
+    V: Clone + Send + 'static,
+{
+    /// Inserts a key-value pair into the list, returning the old value if
+    /// the key was already in the list.
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        let guard = &epoch::pin();
+        let height = Self::_random_height();
+        self.height.fetch_max(height, MemOrdering::SeqCst);
+
+        // The value is owned by `node` until it's published, either by
+        // linking `node` or by replacing the value of an existing node.
+        let value = Owned::new(value).into_shared(guard);
+        let mut node = Owned::new(Node {
+            key,
+            value: Atomic::from(value),
+            refs: AtomicUsize::new(2),
+            tower: (0..height).map(|_| Atomic::null()).collect(),
+        });
+
+        let (n, mut pos) = loop {
+            let pos = self._search(&node.key, guard);
+            if let Some(found) = pos.found {
+                let current = found.value.load(MemOrdering::SeqCst, guard);
+                if current.is_null() {
+                    // Being removed, help to unlink it and try again.
+                    found._mark_tower(guard);
+                    continue;
+                }
+                let replaced = found.value.compare_exchange(
+                    current,
+                    value,
+                    MemOrdering::SeqCst,
+                    MemOrdering::SeqCst,
+                    guard,
+                );
+                if replaced.is_ok() {
+                    node.value = Atomic::null();
+                    let old = unsafe { current.deref().clone() };
+                    unsafe { guard.defer_destroy(current) };
+                    return Some(old);
+                }
+                continue;
+            }
+
+            // Count the node before it's visible, a `remove` of it must not
+            // be able to decrement `len` first.
+            node.tower[0].store(pos.right[0], MemOrdering::Relaxed);
+            self.len.fetch_add(1, MemOrdering::SeqCst);
+            match pos.left[0].compare_exchange(
+                pos.right[0],
+                node,
+                MemOrdering::SeqCst,
+                MemOrdering::SeqCst,
+                guard,
+            ) {
+                Ok(n) => break (unsafe { n.deref() }, pos),
+                Err(e) => {
+                    self.len.fetch_sub(1, MemOrdering::SeqCst);
+                    node = e.new;
+                }
+            }
+        };
+
+        'build: for level in 1..height {
+            loop {
+                let next = n.tower[level].load(MemOrdering::SeqCst, guard);
+                if next.tag() == 1 {
+                    break 'build;
+                }
+                let succ = pos.right[level];
+                let set_next = n.tower[level].compare_exchange(
+                    next,
+                    succ,
+                    MemOrdering::SeqCst,
+                    MemOrdering::SeqCst,
+                    guard,
+                );
+                if set_next.is_err() {
+                    continue;
+                }
+
+                n.refs.fetch_add(1, MemOrdering::SeqCst);
+                let linked = pos.left[level].compare_exchange(
+                    succ,
+                    Shared::from(n as *const Node<K, V>),
+                    MemOrdering::SeqCst,
+                    MemOrdering::SeqCst,
+                    guard,
+                );
+                if linked.is_ok() {
+                    break;
+                }
+                n.refs.fetch_sub(1, MemOrdering::SeqCst);
+
+                pos = self._search(&n.key, guard);
+                match pos.found {
+                    Some(found) if std::ptr::eq(found, n) => (),
+                    _ => break 'build,
+                }
+            }
+        }
+
+        // The node may have been removed while its tower was being built,
+        // make sure no level still links it.
+        if n._is_removed(guard) {
+            self._search(&n.key, guard);
+        }
+        unsafe { n._decrement(guard) };
+
+        None
+    }
+
+    /// Removes a key from the list, returning the value at the key if the
+    /// key was previously in the list.
+    pub fn remove<Q>(&self, q: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let guard = &epoch::pin();
+        loop {
+            let found = self._search(q, guard).found?;
+            let current = found.value.load(MemOrdering::SeqCst, guard);
+            if current.is_null() {
+                // Removed by another thread, help to unlink it and try again.
+                found._mark_tower(guard);
+                continue;
+            }
+            let taken = found.value.compare_exchange(
+                current,
+                Shared::null(),
+                MemOrdering::SeqCst,
+                MemOrdering::SeqCst,
+                guard,
+            );
+            if taken.is_ok() {
+                self.len.fetch_sub(1, MemOrdering::SeqCst);
+                found._mark_tower(guard);
+                self._search(q, guard);
+
+                let value = unsafe { current.deref().clone() };
+                unsafe { guard.defer_destroy(current) };
+                return Some(value);
+            }
+        }
+    }
+
+    /// Returns a clone of the value corresponding to the key.
+    pub fn get<Q>(&self, q: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let guard = &epoch::pin();
+        let found = self._search(q, guard).found?;
+        let value = found.value.load(MemOrdering::SeqCst, guard);
+        unsafe { value.as_ref() }.cloned()
+    }
+
+    /// Returns `true` if the list contains a value for the specified key.
+    pub fn contains_key<Q>(&self, q: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let guard = &epoch::pin();
+        match self._search(q, guard).found {
+            Some(found) => !found.value.load(MemOrdering::SeqCst, guard).is_null(),
+            None => false,
+        }
+    }
+
+    /// Constructs an iterator over a sub-range of entries in the list.
+    ///
+    /// The iterator is weakly consistent: it yields every entry which stays
+    /// in the range during the iteration, entries inserted or removed
+    /// concurrently may or may not be yielded. Memory of removed entries is
+    /// not reclaimed while the iterator is alive.
+    ///
+    /// Panics if range `start > end` or if range `start == end` and both
+    /// bounds are `Excluded`.
+    pub fn range<Q, R>(&self, range: R) -> ConcurrentRange<'_, Q, R, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        _check_range(&range, &OrdComparator);
+
+        let guard = epoch::pin();
+        let front = match range.start_bound() {
+            Bound::Unbounded => self.head[0].load(MemOrdering::SeqCst, &guard),
+            Bound::Included(key) | Bound::Excluded(key) => self._search(key, &guard).right[0],
+        }
+        .as_raw();
+
+        ConcurrentRange {
+            guard,
+            front,
+            range,
+            _list: PhantomData,
+            _query: PhantomData,
+        }
+    }
+
+    /// Constructs an iterator over all entries of the list, see `range`.
+    pub fn iter(&self) -> ConcurrentRange<'_, K, RangeFull, K, V> {
+        self.range(..)
+    }
+
+    // Search for `q`, unlinking removed nodes along the way.
+    fn _search<'g, Q>(&'g self, q: &Q, guard: &'g Guard) -> Position<'g, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        'retry: loop {
+            let mut pos = Position {
+                found: None,
+                left: [&self.head[0]; DEFAULT_MAX_LEVEL],
+                right: [Shared::null(); DEFAULT_MAX_LEVEL],
+            };
+            for (level, left) in pos.left.iter_mut().enumerate() {
+                *left = &self.head[level];
+            }
+
+            let mut pred: &'g [Atomic<Node<K, V>>] = &self.head;
+            let top = self.height.load(MemOrdering::SeqCst);
+            for level in (0..top).rev() {
+                let mut curr = pred[level].load(MemOrdering::SeqCst, guard);
+                if curr.tag() == 1 {
+                    // `pred` is being removed.
+                    continue 'retry;
+                }
+
+                while let Some(c) = unsafe { curr.as_ref() } {
+                    let succ = c.tower[level].load(MemOrdering::SeqCst, guard);
+                    if succ.tag() == 1 {
+                        let unlinked = pred[level].compare_exchange(
+                            curr,
+                            succ.with_tag(0),
+                            MemOrdering::SeqCst,
+                            MemOrdering::SeqCst,
+                            guard,
+                        );
+                        match unlinked {
+                            Ok(_) => {
+                                unsafe { c._decrement(guard) };
+                                curr = succ.with_tag(0);
+                                continue;
+                            }
+                            Err(_) => continue 'retry,
+                        }
+                    }
+
+                    match c.key.borrow().cmp(q) {
+                        Ordering::Less => {
+                            pred = &c.tower;
+                            curr = succ;
+                        }
+                        Ordering::Equal => {
+                            if level == 0 {
+                                pos.found = Some(c);
+                            }
+                            break;
+                        }
+                        Ordering::Greater => break,
+                    }
+                }
+
+                pos.left[level] = &pred[level];
+                pos.right[level] = curr;
+            }
+
+            return pos;
+        }
+    }
+}
+
+impl<K, V> Drop for ConcurrentSkipList<K, V> {
+    fn drop(&mut self) {
+        // No other thread can access the list anymore, and removed nodes are
+        // unlinked from every level already.
+        let guard = unsafe { epoch::unprotected() };
+        let mut curr = self.head[0].load(MemOrdering::Relaxed, guard);
+        while !curr.is_null() {
+            let next = unsafe { curr.deref() }.tower[0].load(MemOrdering::Relaxed, guard);
+            drop(unsafe { curr.into_owned() });
+            curr = next.with_tag(0);
+        }
+    }
+}
+
+impl<K, V> Default for ConcurrentSkipList<K, V> {
+    fn default() -> ConcurrentSkipList<K, V> {
+        ConcurrentSkipList::new()
+    }
+}
+
+/// An iterator over a sub-range of entries in a `ConcurrentSkipList`.
+///
+/// The thread stays pinned to the current epoch until the iterator is
+/// dropped.
+pub struct ConcurrentRange<'a, Q: ?Sized, R, K, V> {
+    guard: Guard,
+    front: *const Node<K, V>,
+    range: R,
+    _list: PhantomData<&'a ConcurrentSkipList<K, V>>,
+    _query: PhantomData<fn(&Q)>,
+}
+
+impl<'a, Q, R, K, V> Iterator for ConcurrentRange<'a, Q, R, K, V>
+where
+    K: Borrow<Q> + Clone,
+    V: Clone,
+    Q: Ord + ?Sized,
+    R: RangeBounds<Q>,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = unsafe { self.front.as_ref()? };
+            let succ = node.tower[0].load(MemOrdering::SeqCst, &self.guard);
+            let key = node.key.borrow();
+
+            let beyond = match self.range.end_bound() {
+                Bound::Included(end) => key > end,
+                Bound::Excluded(end) => key >= end,
+                Bound::Unbounded => false,
+            };
+            if beyond {
+                self.front = std::ptr::null();
+                return None;
+            }
+            self.front = succ.with_tag(0).as_raw();
+
+            let before = match self.range.start_bound() {
+                Bound::Excluded(start) => key == start,
+                _ => false,
+            };
+            if before || succ.tag() == 1 {
+                continue;
+            }
+
+            let value = node.value.load(MemOrdering::SeqCst, &self.guard);
+            if let Some(value) = unsafe { value.as_ref() } {
+                return Some((node.key.clone(), value.clone()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use std::thread;
+
+    const THREADS: usize = 8;
+
+    #[test]
+    fn same_as_btreemap() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let sk = ConcurrentSkipList::new();
+        let mut bt = BTreeMap::new();
+        for _ in 0..5000 {
+            let k = rng.gen_range(0, 500);
+            match rng.gen_range(0, 4) {
+                0 | 1 => assert_eq!(sk.insert(k, k * 2), bt.insert(k, k * 2)),
+                2 => assert_eq!(sk.remove(&k), bt.remove(&k)),
+                _ => assert_eq!(sk.get(&k), bt.get(&k).cloned()),
+            }
+            assert_eq!(sk.len(), bt.len());
+        }
+        assert!(sk.iter().eq(bt.iter().map(|(k, v)| (*k, *v))));
+        for _ in 0..100 {
+            let a = rng.gen_range(0, 500);
+            let b = rng.gen_range(a, 501);
+            assert!(sk.range(a..b).eq(bt.range(a..b).map(|(k, v)| (*k, *v))));
+            assert!(sk.range(a..=b).eq(bt.range(a..=b).map(|(k, v)| (*k, *v))));
+            let excluded = (Bound::Excluded(a), Bound::Unbounded);
+            assert!(sk.range(excluded).eq(bt.range(excluded).map(|(k, v)| (*k, *v))));
+        }
+    }
+
+    #[test]
+    fn concurrent_insert() {
+        let sk = Arc::new(ConcurrentSkipList::new());
+        let handles: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let sk = sk.clone();
+                thread::spawn(move || {
+                    for i in 0..2000 {
+                        assert_eq!(sk.insert(i * THREADS + t, t), None);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(sk.len(), 2000 * THREADS);
+        assert!(sk.iter().map(|(k, _)| k).eq(0..2000 * THREADS));
+        assert!(sk.iter().all(|(k, v)| k % THREADS == v));
+    }
+
+    // Every successful insert of a new key is balanced by a successful
+    // remove, whatever the interleaving is. Concurrent scans must stay
+    // sorted.
+    #[test]
+    fn linearizable_counts() {
+        const KEYS: usize = 64;
+        let sk = Arc::new(ConcurrentSkipList::new());
+        let handles: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let sk = sk.clone();
+                thread::spawn(move || {
+                    let mut rng = StdRng::seed_from_u64(t as u64);
+                    let mut counts = vec![0i64; KEYS];
+                    for _ in 0..20000 {
+                        let k = rng.gen_range(0, KEYS);
+                        match rng.gen_range(0, 5) {
+                            0 | 1 => {
+                                if sk.insert(k, t).is_none() {
+                                    counts[k] += 1;
+                                }
+                            }
+                            2 | 3 => {
+                                if sk.remove(&k).is_some() {
+                                    counts[k] -= 1;
+                                }
+                            }
+                            _ => {
+                                let keys: Vec<_> = sk.range(k..).map(|(k, _)| k).collect();
+                                assert!(keys.windows(2).all(|w| w[0] < w[1]));
+                            }
+                        }
+                    }
+                    counts
+                })
+            })
+            .collect();
+
+        let mut counts = vec![0i64; KEYS];
+        for handle in handles {
+            for (total, count) in counts.iter_mut().zip(handle.join().unwrap()) {
+                *total += count;
+            }
+        }
+        for (k, count) in counts.iter().enumerate() {
+            assert_eq!(*count, sk.contains_key(&k) as i64);
+        }
+        assert_eq!(sk.len(), sk.iter().count());
+    }
+
+    // Concurrent replaces of the same key return every value but the last.
+    #[test]
+    fn concurrent_replace() {
+        let sk = Arc::new(ConcurrentSkipList::new());
+        let handles: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let sk = sk.clone();
+                thread::spawn(move || {
+                    let mut old = vec![];
+                    for i in 0..1000 {
+                        old.extend(sk.insert(0, t * 1000 + i));
+                    }
+                    old
+                })
+            })
+            .collect();
+
+        let mut seen: Vec<_> = handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        seen.extend(sk.get(&0));
+        seen.sort_unstable();
+        assert!(seen.into_iter().eq(0..THREADS * 1000));
+        assert_eq!(sk.len(), 1);
+    }
+
+    // A remove racing with the insert of the same key must never make `len`
+    // run below zero, which would wrap to `usize::MAX`.
+    #[test]
+    fn len_never_wraps() {
+        let sk = Arc::new(ConcurrentSkipList::new());
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let sk = sk.clone();
+                thread::spawn(move || {
+                    for i in 0..20000 {
+                        if t == 0 {
+                            sk.insert(0, i);
+                        } else {
+                            sk.remove(&0);
+                        }
+                        assert!(sk.len() <= 1);
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(sk.len(), sk.iter().count());
+    }
+
+    #[test]
+    fn reclaim() {
+        let value = Arc::new(());
+        let sk = ConcurrentSkipList::new();
+        for i in 0..1000 {
+            sk.insert(i, value.clone());
+        }
+        for i in 0..500 {
+            sk.remove(&i);
+            sk.insert(i + 500, value.clone());
+        }
+        drop(sk);
+
+        // Removed values are destroyed once the epoch advances.
+        for _ in 0..1000 {
+            if Arc::strong_count(&value) == 1 {
+                break;
+            }
+            epoch::pin().flush();
+        }
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+}
@@ -0,0 +1,33 @@
+use core::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SkipListError {
+    /// The key at this index of the input is less than the key before it.
+    UnsortedKey(usize),
+    /// A key inserted through a cursor doesn't fit between its neighbours.
+    KeyOutOfOrder,
+    /// A score of a sorted set is, or would become, NaN.
+    NanScore,
+    /// Sorted set flags which can't be used together.
+    IncompatibleFlags,
+}
+
+impl fmt::Display for SkipListError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SkipListError::UnsortedKey(index) => {
+                write!(f, "key at index {} is less than the previous key", index)
+            }
+            SkipListError::KeyOutOfOrder => {
+                write!(f, "key is out of order with the keys around the cursor")
+            }
+            SkipListError::NanScore => write!(f, "score is not a number (NaN)"),
+            SkipListError::IncompatibleFlags => {
+                write!(f, "NX and XX, or more than one of NX, GT and LT, are not compatible")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SkipListError {}
@@ -0,0 +1,386 @@
+use crate::skip::{Range, SkipList};
+use core::iter::FusedIterator;
+use std::borrow::Borrow;
+use std::mem;
+use std::ops::RangeBounds;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A source of the current time for an `ExpiringSkipList`.
+pub trait Clock {
+    /// Returns the current time.
+    fn now(&self) -> Instant;
+}
+
+/// The clock reading `Instant::now`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock which only moves when told to, for tests. Clones share the same
+/// time.
+#[derive(Clone, Debug)]
+pub struct ManualClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl ManualClock {
+    /// Creates a clock stopped at the current time.
+    pub fn new() -> ManualClock {
+        ManualClock::starting_at(Instant::now())
+    }
+
+    /// Creates a clock stopped at `now`.
+    pub fn starting_at(now: Instant) -> ManualClock {
+        ManualClock {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    /// Moves the time forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> ManualClock {
+        ManualClock::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// An ordered map whose entries may expire, for caches.
+///
+/// An entry inserted by `insert_with_ttl` expires once its time to live has
+/// passed on the list's `Clock`. Reads skip expired entries but leave them
+/// in place, `evict_expired` removes them in bulk. Deadlines are kept in a
+/// second list sorted by time, so eviction only visits expired entries.
+///
+/// ```
+/// use skiplist::{Clock, ExpiringSkipList, ManualClock};
+/// use std::time::Duration;
+///
+/// let clock = ManualClock::new();
+/// let mut cache = ExpiringSkipList::with_clock(clock.clone());
+/// cache.insert_with_ttl("session", 1, Duration::from_secs(60));
+/// cache.insert("config", 2);
+///
+/// clock.advance(Duration::from_secs(60));
+/// assert_eq!(cache.get(&"session"), None);
+/// assert_eq!(cache.get(&"config"), Some(&2));
+/// assert_eq!(cache.len(), 2);
+/// assert_eq!(cache.evict_expired(clock.now()), 1);
+/// assert_eq!(cache.len(), 1);
+/// ```
+pub struct ExpiringSkipList<K, V, T = SystemClock> {
+    // Values with their deadlines, `None` for entries which don't expire.
+    entries: SkipList<K, (V, Option<Instant>)>,
+    deadlines: SkipList<(Instant, K), ()>,
+    clock: T,
+}
+
+impl<K, V> ExpiringSkipList<K, V> {
+    /// Creates an empty list on the system clock.
+    pub fn new() -> ExpiringSkipList<K, V> {
+        ExpiringSkipList::with_clock(SystemClock)
+    }
+}
+
+impl<K, V, T: Clock> ExpiringSkipList<K, V, T> {
+    /// Creates an empty list reading the time from `clock`.
+    pub fn with_clock(clock: T) -> ExpiringSkipList<K, V, T> {
+        ExpiringSkipList {
+            entries: SkipList::new(),
+            deadlines: SkipList::new(),
+            clock,
+        }
+    }
+
+    /// Returns the clock of the list.
+    pub fn clock(&self) -> &T {
+        &self.clock
+    }
+
+    /// Returns the number of entries, counting expired entries which are not
+    /// evicted yet.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the list has no entries, expired or not.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Removes all entries.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.deadlines.clear();
+    }
+}
+
+impl<K: Ord + Clone, V, T: Clock> ExpiringSkipList<K, V, T> {
+    /// Inserts an entry which never expires, returning the old value if the
+    /// key had one which had not expired.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self._insert(key, value, None)
+    }
+
+    /// Inserts an entry expiring `ttl` from now, returning the old value if
+    /// the key had one which had not expired. A deadline too far away for
+    /// an `Instant`, like with `Duration::MAX`, never comes.
+    pub fn insert_with_ttl(&mut self, key: K, value: V, ttl: Duration) -> Option<V> {
+        let deadline = self.clock.now().checked_add(ttl);
+        self._insert(key, value, deadline)
+    }
+
+    fn _insert(&mut self, key: K, value: V, deadline: Option<Instant>) -> Option<V> {
+        if let Some(deadline) = deadline {
+            self.deadlines.insert((deadline, key.clone()), ());
+        }
+        let entry = match self.entries.get_mut(&key) {
+            Some(entry) => entry,
+            None => {
+                self.entries.insert(key, (value, deadline));
+                return None;
+            }
+        };
+        let (old, old_deadline) = mem::replace(entry, (value, deadline));
+        // The old deadline doesn't apply to the new value.
+        if let Some(old_deadline) = old_deadline {
+            if Some(old_deadline) != deadline {
+                self.deadlines.remove(&(old_deadline, key));
+            }
+        }
+        self._live(old, old_deadline)
+    }
+
+    /// Returns the value of `q` if it has not expired.
+    pub fn get<Q>(&self, q: &Q) -> Option<&V>
+    where
+        Q: ?Sized + Ord,
+        K: Borrow<Q>,
+    {
+        let (value, deadline) = self.entries.get(q)?;
+        if _expired(*deadline, self.clock.now()) {
+            return None;
+        }
+        Some(value)
+    }
+
+    /// Returns the value of `q` mutably if it has not expired.
+    pub fn get_mut<Q>(&mut self, q: &Q) -> Option<&mut V>
+    where
+        Q: ?Sized + Ord,
+        K: Borrow<Q>,
+    {
+        let now = self.clock.now();
+        let (value, deadline) = self.entries.get_mut(q)?;
+        if _expired(*deadline, now) {
+            return None;
+        }
+        Some(value)
+    }
+
+    /// Returns `true` if `q` has a value which has not expired.
+    pub fn contains_key<Q>(&self, q: &Q) -> bool
+    where
+        Q: ?Sized + Ord,
+        K: Borrow<Q>,
+    {
+        self.get(q).is_some()
+    }
+
+    /// Returns how long the entry of `q` has to live, `None` if it has
+    /// expired or doesn't exist and `Some(None)` if it never expires.
+    pub fn ttl<Q>(&self, q: &Q) -> Option<Option<Duration>>
+    where
+        Q: ?Sized + Ord,
+        K: Borrow<Q>,
+    {
+        let (_, deadline) = self.entries.get(q)?;
+        let now = self.clock.now();
+        match deadline {
+            Some(deadline) if *deadline <= now => None,
+            Some(deadline) => Some(Some(*deadline - now)),
+            None => Some(None),
+        }
+    }
+
+    /// Removes `q`, returning its value if it had not expired.
+    pub fn remove<Q>(&mut self, q: &Q) -> Option<V>
+    where
+        Q: ?Sized + Ord,
+        K: Borrow<Q>,
+    {
+        let (key, (value, deadline)) = self.entries.remove_entry(q)?;
+        if let Some(deadline) = deadline {
+            self.deadlines.remove(&(deadline, key));
+        }
+        self._live(value, deadline)
+    }
+
+    /// Iterates over the entries inside `range` which have not expired.
+    ///
+    /// Panics if range `start > end` or if range `start == end` and both
+    /// bounds are `Excluded`.
+    pub fn range<Q, R>(&self, range: R) -> ExpiringRange<'_, K, V>
+    where
+        Q: ?Sized + Ord,
+        K: Borrow<Q>,
+        R: RangeBounds<Q>,
+    {
+        ExpiringRange {
+            inner: self.entries.range(range),
+            now: self.clock.now(),
+        }
+    }
+
+    /// Iterates over the entries which have not expired, in key order.
+    pub fn iter(&self) -> ExpiringRange<'_, K, V> {
+        self.range::<K, _>(..)
+    }
+
+    /// Removes every entry expired at `now`, returning how many were
+    /// removed.
+    pub fn evict_expired(&mut self, now: Instant) -> usize {
+        let mut evicted = 0;
+        while let Some((&(deadline, _), _)) = self.deadlines.first_key_value() {
+            if deadline > now {
+                break;
+            }
+            let ((_, key), _) = self.deadlines.pop_first().unwrap();
+            self.entries.remove(&key);
+            evicted += 1;
+        }
+        evicted
+    }
+
+    fn _live(&self, value: V, deadline: Option<Instant>) -> Option<V> {
+        if _expired(deadline, self.clock.now()) {
+            None
+        } else {
+            Some(value)
+        }
+    }
+}
+
+// An entry expires when its deadline comes.
+fn _expired(deadline: Option<Instant>, now: Instant) -> bool {
+    deadline.is_some_and(|deadline| deadline <= now)
+}
+
+impl<K, V> Default for ExpiringSkipList<K, V> {
+    fn default() -> ExpiringSkipList<K, V> {
+        ExpiringSkipList::new()
+    }
+}
+
+/// An iterator over the entries of an `ExpiringSkipList` which have not
+/// expired, as of its creation.
+pub struct ExpiringRange<'a, K, V> {
+    inner: Range<'a, K, (V, Option<Instant>)>,
+    now: Instant,
+}
+
+impl<'a, K, V> Iterator for ExpiringRange<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let now = self.now;
+        self.inner
+            .by_ref()
+            .find(|(_, (_, deadline))| !_expired(*deadline, now))
+            .map(|(k, (v, _))| (k, v))
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for ExpiringRange<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let now = self.now;
+        self.inner
+            .by_ref()
+            .rfind(|(_, (_, deadline))| !_expired(*deadline, now))
+            .map(|(k, (v, _))| (k, v))
+    }
+}
+
+impl<'a, K, V> FusedIterator for ExpiringRange<'a, K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expiry_and_eviction() {
+        let clock = ManualClock::new();
+        let mut sk = ExpiringSkipList::with_clock(clock.clone());
+        for i in 0..10 {
+            sk.insert_with_ttl(i, i * 10, Duration::from_secs(i as u64 + 1));
+        }
+        sk.insert(10, 100);
+        assert_eq!(sk.ttl(&3), Some(Some(Duration::from_secs(4))));
+        assert_eq!(sk.ttl(&10), Some(None));
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(sk.get(&3), None);
+        assert_eq!(sk.ttl(&3), None);
+        assert_eq!(sk.get_mut(&5), Some(&mut 50));
+        assert!(sk.contains_key(&10));
+        assert!(sk.iter().map(|(k, _)| *k).eq(5..11));
+        assert!(sk.range(2..7).rev().map(|(k, _)| *k).eq((5..7).rev()));
+        assert_eq!(sk.remove(&0), None);
+        assert_eq!(sk.len(), 10);
+
+        assert_eq!(sk.evict_expired(clock.now()), 4);
+        assert_eq!(sk.len(), 6);
+        assert_eq!(sk.deadlines.len(), 5);
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(sk.evict_expired(clock.now()), 5);
+        assert!(sk.iter().map(|(k, _)| *k).eq(10..11));
+    }
+
+    #[test]
+    fn huge_ttl() {
+        let clock = ManualClock::new();
+        let mut sk = ExpiringSkipList::with_clock(clock.clone());
+        sk.insert_with_ttl(1, "forever", Duration::MAX);
+        assert_eq!(sk.ttl(&1), Some(None));
+        assert!(sk.deadlines.is_empty());
+        clock.advance(Duration::from_secs(1 << 40));
+        assert_eq!(sk.evict_expired(clock.now()), 0);
+        assert_eq!(sk.get(&1), Some(&"forever"));
+    }
+
+    #[test]
+    fn reinsert() {
+        let clock = ManualClock::new();
+        let mut sk = ExpiringSkipList::with_clock(clock.clone());
+        sk.insert_with_ttl("a", 1, Duration::from_secs(1));
+        assert_eq!(sk.insert_with_ttl("a", 2, Duration::from_secs(10)), Some(1));
+        assert_eq!(sk.deadlines.len(), 1);
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(sk.evict_expired(clock.now()), 0);
+        assert_eq!(sk.get(&"a"), Some(&2));
+
+        // An expired value is not returned when replaced.
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(sk.insert("a", 3), None);
+        assert!(sk.deadlines.is_empty());
+        clock.advance(Duration::from_secs(100));
+        assert_eq!(sk.get(&"a"), Some(&3));
+        assert_eq!(sk.remove(&"a"), Some(3));
+        assert!(sk.is_empty());
+    }
+}
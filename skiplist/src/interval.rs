@@ -0,0 +1,525 @@
+use crate::level::GeometricalLevelGenerator;
+use crate::level::LevelGenerator;
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+// Null link.
+const NIL: usize = usize::MAX;
+// The head when standing for a pre node.
+const HEAD: usize = usize::MAX - 1;
+
+// A node for every distinct endpoint of the intervals.
+struct Node<K> {
+    key: K,
+    nexts: Vec<usize>,
+    // Markers of the edge to `nexts[l]`: intervals covering the open span
+    // between the two keys.
+    markers: Vec<Vec<usize>>,
+    // Intervals containing the key.
+    eq: Vec<usize>,
+    // Intervals starting at the key.
+    starts: Vec<usize>,
+    // Number of interval endpoints at the key, the node goes with the last.
+    refs: usize,
+}
+
+struct Interval<K, V> {
+    range: Range<K>,
+    value: V,
+    generation: u64,
+}
+
+/// A handle to an interval in an `IntervalSkipList`, returned by `insert`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct IntervalId {
+    index: usize,
+    generation: u64,
+}
+
+/// A collection of half-open intervals `[start, end)` with values, answering
+/// which intervals contain a point or overlap a range.
+///
+/// This is the interval skip list of Hanson: the endpoints are the keys of a
+/// skip list, and an interval puts markers on the few tower edges which
+/// together cover it, and on the nodes it contains. The search path of a
+/// point crosses a single edge on every level, so `stab` only reads the
+/// markers of `O(log n)` edges, and every marker it reads is an answer.
+/// `stab` and `overlapping` take `O(log n + k)` for `k` answers. The same
+/// interval may be inserted more than once.
+///
+/// ```
+/// use skiplist::IntervalSkipList;
+///
+/// let mut windows = IntervalSkipList::new();
+/// windows.insert(0..10, "backup");
+/// let deploy = windows.insert(5..15, "deploy");
+/// windows.insert(20..30, "upgrade");
+///
+/// let mut at_7: Vec<_> = windows.stab(&7).into_iter().map(|(_, _, v)| *v).collect();
+/// at_7.sort();
+/// assert_eq!(at_7, vec!["backup", "deploy"]);
+/// assert_eq!(windows.overlapping(12..25).len(), 2);
+///
+/// assert_eq!(windows.remove(deploy), Some((5..15, "deploy")));
+/// assert_eq!(windows.stab(&12).len(), 0);
+/// ```
+pub struct IntervalSkipList<K, V> {
+    nodes: Vec<Option<Node<K>>>,
+    free_nodes: Vec<usize>,
+    head: Vec<usize>,
+    intervals: Vec<Option<Interval<K, V>>>,
+    free_intervals: Vec<usize>,
+    generation: u64,
+    level_generator: Box<dyn LevelGenerator>,
+}
+
+impl<K, V> IntervalSkipList<K, V> {
+    /// Creates an empty list with the default `GeometricalLevelGenerator`,
+    /// which is seeded from the thread RNG, or with a fixed seed without
+    /// `std`.
+    pub fn new() -> IntervalSkipList<K, V> {
+        IntervalSkipList::with_level_generator(GeometricalLevelGenerator::default())
+    }
+
+    /// Creates an empty list whose tower heights are chosen by
+    /// `level_generator`.
+    pub fn with_level_generator<G>(level_generator: G) -> IntervalSkipList<K, V>
+    where
+        G: LevelGenerator + 'static,
+    {
+        IntervalSkipList {
+            nodes: vec![],
+            free_nodes: vec![],
+            head: vec![],
+            intervals: vec![],
+            free_intervals: vec![],
+            generation: 0,
+            level_generator: Box::new(level_generator),
+        }
+    }
+
+    /// Returns the number of intervals.
+    pub fn len(&self) -> usize {
+        self.intervals.len() - self.free_intervals.len()
+    }
+
+    /// Returns `true` if there are no intervals.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the interval and value of `id`, `None` if it was removed.
+    pub fn get(&self, id: IntervalId) -> Option<(&Range<K>, &V)> {
+        match self.intervals.get(id.index) {
+            Some(Some(interval)) if interval.generation == id.generation => {
+                Some((&interval.range, &interval.value))
+            }
+            _ => None,
+        }
+    }
+
+    fn _node(&self, index: usize) -> &Node<K> {
+        self.nodes[index].as_ref().expect("node is occupied")
+    }
+
+    fn _node_mut(&mut self, index: usize) -> &mut Node<K> {
+        self.nodes[index].as_mut().expect("node is occupied")
+    }
+
+    fn _range(&self, index: usize) -> &Range<K> {
+        &self.intervals[index]
+            .as_ref()
+            .expect("interval is occupied")
+            .range
+    }
+
+    fn _next(&self, x: usize, level: usize) -> usize {
+        if x == HEAD {
+            self.head[level]
+        } else {
+            self._node(x).nexts[level]
+        }
+    }
+
+    fn _set_next(&mut self, x: usize, level: usize, next: usize) {
+        if x == HEAD {
+            self.head[level] = next;
+        } else {
+            self._node_mut(x).nexts[level] = next;
+        }
+    }
+
+    fn _answers(&self, indexes: Vec<usize>) -> Vec<(IntervalId, &Range<K>, &V)> {
+        indexes
+            .into_iter()
+            .map(|index| {
+                let interval = self.intervals[index]
+                    .as_ref()
+                    .expect("interval is occupied");
+                let id = IntervalId {
+                    index,
+                    generation: interval.generation,
+                };
+                (id, &interval.range, &interval.value)
+            })
+            .collect()
+    }
+}
+
+impl<K: Ord + Clone, V> IntervalSkipList<K, V> {
+    /// Inserts the interval `[range.start, range.end)` with `value`.
+    ///
+    /// Panics if the interval is empty, `range.start >= range.end`.
+    pub fn insert(&mut self, range: Range<K>, value: V) -> IntervalId {
+        assert!(
+            range.start < range.end,
+            "interval start must be less than its end"
+        );
+        self.generation += 1;
+        let interval = Interval {
+            range: range.clone(),
+            value,
+            generation: self.generation,
+        };
+        let index = match self.free_intervals.pop() {
+            Some(index) => {
+                self.intervals[index] = Some(interval);
+                index
+            }
+            None => {
+                self.intervals.push(Some(interval));
+                self.intervals.len() - 1
+            }
+        };
+
+        let start = self._acquire(range.start);
+        self._node_mut(start).starts.push(index);
+        self._acquire(range.end);
+        self._place(index);
+        IntervalId {
+            index,
+            generation: self.generation,
+        }
+    }
+
+    /// Removes the interval of `id`, returning it with its value, or `None`
+    /// if it was removed already.
+    pub fn remove(&mut self, id: IntervalId) -> Option<(Range<K>, V)> {
+        self.get(id)?;
+        self._unplace(id.index);
+        let interval = self.intervals[id.index].take().unwrap();
+        self.free_intervals.push(id.index);
+
+        let start = self._find(&interval.range.start);
+        let starts = &mut self._node_mut(start).starts;
+        let at = starts.iter().position(|i| *i == id.index).unwrap();
+        starts.swap_remove(at);
+        self._release(&interval.range.start);
+        self._release(&interval.range.end);
+        Some((interval.range, interval.value))
+    }
+
+    /// Returns the intervals containing `point`, in no particular order.
+    pub fn stab(&self, point: &K) -> Vec<(IntervalId, &Range<K>, &V)> {
+        let mut found = vec![];
+        let mut x = HEAD;
+        for level in (0..self.head.len()).rev() {
+            let mut next = self._next(x, level);
+            while next != NIL && self._node(next).key < *point {
+                x = next;
+                next = self._next(x, level);
+            }
+            if next == NIL {
+                continue;
+            }
+            let node = self._node(next);
+            if node.key == *point {
+                // No edge below spans the point, it is a node on them.
+                found.extend_from_slice(&node.eq);
+                break;
+            }
+            // The edge the search drops down from spans the point.
+            if x != HEAD {
+                found.extend_from_slice(&self._node(x).markers[level]);
+            }
+        }
+        self._answers(found)
+    }
+
+    /// Returns the intervals overlapping `[range.start, range.end)`, in no
+    /// particular order.
+    pub fn overlapping(&self, range: Range<K>) -> Vec<(IntervalId, &Range<K>, &V)> {
+        if range.start >= range.end || self.head.is_empty() {
+            return vec![];
+        }
+        // The intervals containing the start, and then the intervals
+        // starting inside the range. Every endpoint inside the range
+        // belongs to an answer, so the walk takes `O(k)`.
+        let mut found: Vec<_> = self
+            .stab(&range.start)
+            .into_iter()
+            .map(|(id, _, _)| id.index)
+            .collect();
+        let mut x = self._pre(&range.start)[0];
+        x = self._next(x, 0);
+        while x != NIL && self._node(x).key < range.end {
+            let node = self._node(x);
+            if node.key > range.start {
+                found.extend_from_slice(&node.starts);
+            }
+            x = node.nexts[0];
+        }
+        self._answers(found)
+    }
+
+    // The last node before `key` on every level, the head if there is none.
+    fn _pre(&self, key: &K) -> Vec<usize> {
+        let mut pres = vec![HEAD; self.head.len()];
+        let mut x = HEAD;
+        for level in (0..self.head.len()).rev() {
+            let mut next = self._next(x, level);
+            while next != NIL && self._node(next).key < *key {
+                x = next;
+                next = self._next(x, level);
+            }
+            pres[level] = x;
+        }
+        pres
+    }
+
+    // The node of an endpoint which is in the list.
+    fn _find(&self, key: &K) -> usize {
+        let pre = self._pre(key)[0];
+        self._next(pre, 0)
+    }
+
+    // Returns the node of `key`, inserting it if it is new, and counts one
+    // more endpoint at it.
+    fn _acquire(&mut self, key: K) -> usize {
+        let mut pres = self._pre(&key);
+        let found = match pres.first() {
+            Some(pre) => self._next(*pre, 0),
+            None => NIL,
+        };
+        if found != NIL && self._node(found).key == key {
+            self._node_mut(found).refs += 1;
+            return found;
+        }
+
+        // The list grows one level at most for every insertion.
+        let height = (self.level_generator.random() + 1).min(self.head.len() + 1);
+        if height > self.head.len() {
+            self.head.push(NIL);
+            pres.push(HEAD);
+        }
+        let mut node = Node {
+            key,
+            nexts: Vec::with_capacity(height),
+            markers: Vec::with_capacity(height),
+            eq: vec![],
+            starts: vec![],
+            refs: 1,
+        };
+        // A split edge keeps its markers on both halves, and its intervals
+        // now contain the new node. An interval has one edge spanning the
+        // key, so it is added once.
+        for (level, pre) in pres.iter().enumerate().take(height) {
+            node.nexts.push(self._next(*pre, level));
+            let markers = if *pre == HEAD {
+                vec![]
+            } else {
+                self._node(*pre).markers[level].clone()
+            };
+            node.eq.extend_from_slice(&markers);
+            node.markers.push(markers);
+        }
+
+        let index = match self.free_nodes.pop() {
+            Some(index) => {
+                self.nodes[index] = Some(node);
+                index
+            }
+            None => {
+                self.nodes.push(Some(node));
+                self.nodes.len() - 1
+            }
+        };
+        for (level, pre) in pres.iter().enumerate().take(height) {
+            self._set_next(*pre, level, index);
+        }
+        index
+    }
+
+    // Counts one less endpoint at `key`, removing its node after the last.
+    fn _release(&mut self, key: &K) {
+        let pres = self._pre(key);
+        let index = self._next(pres[0], 0);
+        let node = self._node_mut(index);
+        node.refs -= 1;
+        if node.refs > 0 {
+            return;
+        }
+
+        // Not being an endpoint, the node is inside the intervals marking
+        // its edges. They are taken off and placed again over the merged
+        // edges.
+        let affected = node.eq.clone();
+        for interval in &affected {
+            self._unplace(*interval);
+        }
+        let node = self.nodes[index].take().unwrap();
+        self.free_nodes.push(index);
+        for (level, next) in node.nexts.iter().enumerate() {
+            self._set_next(pres[level], level, *next);
+        }
+        while self.head.last() == Some(&NIL) {
+            self.head.pop();
+        }
+        for interval in affected {
+            self._place(interval);
+        }
+    }
+
+    // Marks the edges covering an interval, from its start taking the
+    // highest edge not going past its end.
+    fn _place(&mut self, interval: usize) {
+        let Range { start, end } = self._range(interval).clone();
+        let mut x = self._find(&start);
+        while self._node(x).key < end {
+            let node = self._node(x);
+            let level = (0..node.nexts.len())
+                .rev()
+                .find(|l| node.nexts[*l] != NIL && self._node(node.nexts[*l]).key <= end)
+                .expect("the end is a node after the start");
+            let next = node.nexts[level];
+            let node = self._node_mut(x);
+            node.eq.push(interval);
+            node.markers[level].push(interval);
+            x = next;
+        }
+    }
+
+    // Takes the markers of an interval off, following its marked edges.
+    fn _unplace(&mut self, interval: usize) {
+        let Range { start, end } = self._range(interval).clone();
+        let mut x = self._find(&start);
+        while self._node(x).key < end {
+            let node = self._node_mut(x);
+            _remove_marker(&mut node.eq, interval);
+            let level = (0..node.markers.len())
+                .rev()
+                .find(|l| node.markers[*l].contains(&interval))
+                .expect("an edge of the node is marked");
+            _remove_marker(&mut node.markers[level], interval);
+            x = node.nexts[level];
+        }
+    }
+}
+
+fn _remove_marker(markers: &mut Vec<usize>, interval: usize) {
+    let at = markers
+        .iter()
+        .position(|i| *i == interval)
+        .expect("interval is marked");
+    markers.swap_remove(at);
+}
+
+impl<K, V> Default for IntervalSkipList<K, V> {
+    fn default() -> IntervalSkipList<K, V> {
+        IntervalSkipList::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn ids<V>(found: Vec<(IntervalId, &Range<i32>, &V)>) -> Vec<IntervalId> {
+        let mut ids: Vec<_> = found.into_iter().map(|(id, _, _)| id).collect();
+        ids.sort_by_key(|id| id.index);
+        ids
+    }
+
+    #[test]
+    fn same_as_brute_force() {
+        let mut rng = StdRng::seed_from_u64(23);
+        let mut isl = IntervalSkipList::with_level_generator(GeometricalLevelGenerator::seeded(23));
+        let mut all: Vec<(IntervalId, Range<i32>)> = vec![];
+        for round in 0..3000 {
+            if rng.gen_bool(0.6) || all.is_empty() {
+                let start = rng.gen_range(0, 500);
+                let end = start + rng.gen_range(1, 60);
+                let id = isl.insert(start..end, round);
+                all.push((id, start..end));
+            } else {
+                let (id, range) = all.swap_remove(rng.gen_range(0, all.len()));
+                let (removed, _) = isl.remove(id).unwrap();
+                assert_eq!(removed, range);
+                assert_eq!(isl.remove(id), None);
+            }
+            assert_eq!(isl.len(), all.len());
+
+            if round % 50 == 0 {
+                for point in -1..562 {
+                    let mut expected: Vec<_> = all
+                        .iter()
+                        .filter(|(_, r)| r.contains(&point))
+                        .map(|(id, _)| *id)
+                        .collect();
+                    expected.sort_by_key(|id| id.index);
+                    assert_eq!(ids(isl.stab(&point)), expected);
+                }
+                for _ in 0..50 {
+                    let lo = rng.gen_range(-10, 560);
+                    let hi = lo + rng.gen_range(0, 40);
+                    let mut expected: Vec<_> = all
+                        .iter()
+                        .filter(|(_, r)| r.start < hi && lo < r.end && lo < hi)
+                        .map(|(id, _)| *id)
+                        .collect();
+                    expected.sort_by_key(|id| id.index);
+                    assert_eq!(ids(isl.overlapping(lo..hi)), expected);
+                }
+            }
+        }
+
+        for (id, _) in all.drain(..) {
+            isl.remove(id).unwrap();
+        }
+        assert!(isl.is_empty());
+        assert!(isl.head.is_empty());
+        assert_eq!(isl.free_nodes.len(), isl.nodes.len());
+    }
+
+    #[test]
+    fn shared_endpoints() {
+        let mut isl = IntervalSkipList::new();
+        let a = isl.insert(1..5, 'a');
+        let b = isl.insert(1..5, 'b');
+        let c = isl.insert(5..9, 'c');
+        assert_eq!(isl.get(a), Some((&(1..5), &'a')));
+        assert_eq!(ids(isl.stab(&1)), vec![a, b]);
+        assert_eq!(ids(isl.stab(&5)), vec![c]);
+        assert_eq!(ids(isl.stab(&9)), vec![]);
+        assert_eq!(isl.remove(b), Some((1..5, 'b')));
+        assert_eq!(isl.get(b), None);
+        let d = isl.insert(0..3, 'd');
+        assert_eq!(d.index, b.index);
+        assert_ne!(d, b);
+        assert_eq!(isl.remove(b), None);
+        assert_eq!(ids(isl.stab(&2)), vec![a, d]);
+        assert_eq!(ids(isl.overlapping(4..6)), vec![a, c]);
+        assert_eq!(ids(isl.overlapping(5..5)), vec![]);
+    }
+
+    #[test]
+    #[should_panic(expected = "interval start must be less than its end")]
+    fn empty_interval() {
+        let mut isl = IntervalSkipList::new();
+        isl.insert(3..3, ());
+    }
+}
@@ -0,0 +1,165 @@
+use alloc::boxed::Box;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+
+/// Default upper bound of levels, enough for about 2^32 entries with the
+/// default promotion probability.
+pub const DEFAULT_MAX_LEVEL: usize = 32;
+
+/// Decides the height of the tower of every new node.
+///
+/// Generators are `Send` and `Sync` so a list can move to another thread or
+/// be shared with one.
+pub trait LevelGenerator: Send + Sync {
+    /// The number of levels this generator can produce, so `random` always
+    /// returns a value in `0..total()`.
+    fn total(&self) -> usize;
+
+    /// Returns a random level, 0 is the bottom level.
+    fn random(&mut self) -> usize;
+
+    /// Returns a generator for a list made from this one, like a clone or
+    /// the tail of `split_off`, so it keeps the configuration and a seeded
+    /// list stays reproducible.
+    fn fork(&self) -> Box<dyn LevelGenerator>;
+}
+
+/// Levels with a geometric distribution: a node reaching level `n` is
+/// promoted to level `n + 1` with probability `p`.
+pub struct GeometricalLevelGenerator<R> {
+    total: usize,
+    p: f64,
+    rng: R,
+}
+
+impl<R: RngCore> GeometricalLevelGenerator<R> {
+    /// Creates a generator producing levels in `0..total` from `rng`.
+    ///
+    /// Without `std` this is how lists get levels which differ from run to
+    /// run, from a hardware RNG for example.
+    ///
+    /// Panics if `total` is 0 or `p` is not in `(0, 1)`.
+    pub fn new(total: usize, p: f64, rng: R) -> GeometricalLevelGenerator<R> {
+        assert!(total > 0, "total levels must be greater than 0");
+        assert!(p > 0.0 && p < 1.0, "promotion probability must be in (0, 1)");
+        GeometricalLevelGenerator { total, p, rng }
+    }
+}
+
+impl GeometricalLevelGenerator<StdRng> {
+    /// A generator with `p = 1/2` and a reproducible sequence of levels.
+    pub fn seeded(seed: u64) -> GeometricalLevelGenerator<StdRng> {
+        GeometricalLevelGenerator::new(DEFAULT_MAX_LEVEL, 0.5, StdRng::seed_from_u64(seed))
+    }
+}
+
+/// Seeded from the thread RNG. Without `std` there is no source of entropy
+/// and the seed is fixed, give an RNG to `new` where the same sequence in
+/// every run matters.
+impl Default for GeometricalLevelGenerator<StdRng> {
+    #[cfg(feature = "std")]
+    fn default() -> GeometricalLevelGenerator<StdRng> {
+        let rng = StdRng::from_rng(rand::thread_rng()).unwrap();
+        GeometricalLevelGenerator::new(DEFAULT_MAX_LEVEL, 0.5, rng)
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn default() -> GeometricalLevelGenerator<StdRng> {
+        GeometricalLevelGenerator::seeded(0x5eed_5eed_5eed_5eed)
+    }
+}
+
+// The fork carries on from the current state of the RNG, producing the
+// levels this generator would produce next.
+impl<R: RngCore + Clone + Send + Sync + 'static> LevelGenerator for GeometricalLevelGenerator<R> {
+    fn total(&self) -> usize {
+        self.total
+    }
+
+    fn random(&mut self) -> usize {
+        let mut level = 0;
+        while level + 1 < self.total && self.rng.gen_bool(self.p) {
+            level += 1;
+        }
+        level
+    }
+
+    fn fork(&self) -> Box<dyn LevelGenerator> {
+        Box::new(GeometricalLevelGenerator {
+            total: self.total,
+            p: self.p,
+            rng: self.rng.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn geometrical() {
+        let mut g = GeometricalLevelGenerator::new(4, 0.5, StdRng::seed_from_u64(1));
+        let mut counts = [0; 4];
+        for _ in 0..10000 {
+            counts[g.random()] += 1;
+        }
+        assert!(counts.iter().all(|c| *c > 0));
+        assert!(counts[0] > counts[1] && counts[1] > counts[2]);
+
+        let mut g = GeometricalLevelGenerator::new(1, 0.9, StdRng::seed_from_u64(1));
+        assert!((0..100).all(|_| g.random() == 0));
+    }
+
+    #[test]
+    fn seeded() {
+        let mut a = GeometricalLevelGenerator::seeded(7);
+        let mut b = GeometricalLevelGenerator::seeded(7);
+        for _ in 0..1000 {
+            assert_eq!(a.random(), b.random());
+        }
+        let mut c = a.fork();
+        assert_eq!(c.total(), DEFAULT_MAX_LEVEL);
+        for _ in 0..1000 {
+            assert_eq!(a.random(), c.random());
+        }
+    }
+
+    // A tiny RNG standing in for one supplied by firmware.
+    #[derive(Clone)]
+    struct XorShift(u64);
+
+    impl RngCore for XorShift {
+        fn next_u32(&mut self) -> u32 {
+            self.next_u64() as u32
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            for byte in dest {
+                *byte = self.next_u64() as u8;
+            }
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+            self.fill_bytes(dest);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn user_rng() {
+        let g = GeometricalLevelGenerator::new(DEFAULT_MAX_LEVEL, 0.25, XorShift(0x2545_f491));
+        let mut sk = crate::SkipList::with_level_generator(g);
+        for i in 0..1000 {
+            sk.insert(i, i);
+        }
+        assert!(sk.iter().map(|(k, _)| *k).eq(0..1000));
+    }
+}
@@ -1,152 +1,54 @@
-// use std::cmp::Ord;
-// use std::cmp::Ordering;
-// // use std::collections::BTreeMap;
-// use std::rc::Rc;
-// use std::borrow::Borrow;
-
-// use rand::prelude::*;
-// use rand::random;
-
-// const DEFAULT_MAX_LEVEL: usize = 8;
-
-// struct Node<K, V> {
-//     nexts: Vec<Option<Rc<Box<Node<K, V>>>>>,
-//     key: K,
-//     value: V,
-// }
-
-// struct NodeHead<K, V> {
-//     nexts: Vec<Option<Rc<Box<Node<K, V>>>>>,
-// }
-
-// struct SkipList<K, V> {
-//     head: NodeHead<K, V>,
-//     cnt :u64,
-//     max_level :usize,
-    
-// }
-
-// impl<K, V> SkipList<K, V> where K: Ord {
-//     fn new() -> SkipList<K, V> {
-//         let head = NodeHead::<K, V>{
-//             nexts: vec![None; DEFAULT_MAX_LEVEL]
-//         };
-//         SkipList {
-//             head,
-//             cnt: 0,
-//             max_level: DEFAULT_MAX_LEVEL,
-//         }
-//     }
-
-//     #[allow(dead_code)]
-//     fn get<Q: ?Sized>(&self, q :&Q) -> Option<&V>
-//         where K: Borrow<Q>,
-//               Q: Ord {
-//         let mut level = self.max_level - 1;
-//         let mut nexts = Some(&self.head.nexts);
-//         let mut result = None;
-//         while let Some(_nexts) = nexts {
-//             match &_nexts[level] {
-//                 Some(item) => {
-//                     match q.cmp(item.key.borrow()) {
-//                         Ordering::Equal => {
-//                             nexts = None;
-//                             result = Some(&item.value);
-//                         },
-//                         Ordering::Greater => {
-//                             nexts = Some(&item.nexts);
-//                         },
-//                         Ordering::Less => {
-//                             if level == 0 {
-//                                 break;
-//                             }
-//                             level -= 1;
-//                         }
-//                     }
-//                 },
-//                 None => {
-//                     if level == 0 {
-//                         break
-//                     }
-//                     level -= 1;
-//                 }
-//             }
-//         }
-//         result
-//     }
-
-//     fn insert(&mut self, k :K, v :V) -> Option<V> {
-//         None
-//     }
-
-//     fn get_mut(&mut self, k &K) -> Option<&mut V> {
-//         None
-//     }
-
-//     fn take(&mut self, k &K) -> Option<V> {
-//         None
-//     }
-
-//     fn remove(&mut self, k &K) -> Option<V> {
-//         None
-//     }
-
-//     range
-// }
-
-mod skip;
-
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
 #[cfg(test)]
-mod tests {
-    use std::fmt::Debug;
-    use std::borrow::Borrow;
-    #[derive(Debug)]
-    struct A(i32);
-    fn boo<Q: Borrow<A>+Debug>(q: &Q) {
-        println!("-- {:?}", q);
-    }
-
-    #[derive(Debug)]
-    struct Val(i32);
-
-    #[derive(Debug)]
-    struct Item {
-        val :Val,
-    }
-
-    impl Item {
-        fn f1(&self) -> &Val {
-            &self.val
-        }
-
-        fn f2(&mut self) -> &mut Val {
-            self.val = Val(self.val.0+1);
-            &mut self.val
-        }
-
-        fn f3(&mut self) {
-            self.val = Val(self.val.0+1);
-        }
-
-        fn f4(&mut self) {
-            println!("x");
-        }
-    }
-
-    #[test]
-    fn t_f() {
-        let mut item = Item{val: Val(1)};
-        // let a = item.f1();
-        item.f4();
-        item.f3();
-        // println!("a {:?}", *a);
-    }
-
-    #[test]
-    fn it_works() {
-        assert_eq!(2 + 2, 4);
-        let a = A(10);
-        boo(&a)
-    }
-}
+extern crate std;
+
+mod arena;
+mod comparator;
+#[cfg(feature = "std")]
+mod concurrent;
+mod error;
+#[cfg(feature = "std")]
+mod expiring;
+mod interval;
+mod level;
+#[cfg(feature = "std")]
+mod lsm;
+mod multimap;
+#[cfg(feature = "std")]
+mod mvcc;
+#[cfg(feature = "serde")]
+mod serde_impl;
+mod set;
+#[cfg(feature = "std")]
+mod sharded;
+mod skip;
+#[cfg(feature = "std")]
+mod sorted_set;
+
+pub use arena::{ArenaIter, ArenaRange, ArenaSkipList};
+pub use comparator::{Comparator, OrdComparator, ReverseComparator};
+#[cfg(feature = "std")]
+pub use concurrent::{ConcurrentRange, ConcurrentSkipList};
+pub use error::SkipListError;
+#[cfg(feature = "std")]
+pub use expiring::{Clock, ExpiringRange, ExpiringSkipList, ManualClock, SystemClock};
+pub use interval::{IntervalId, IntervalSkipList};
+pub use level::{GeometricalLevelGenerator, LevelGenerator, DEFAULT_MAX_LEVEL};
+#[cfg(feature = "std")]
+pub use lsm::{Memtable, MemtableRange, MergeIter, MergeSource, SsTable, SsTableRange};
+pub use multimap::{GetAll, MultiIter, MultiRange, SkipMultiMap};
+#[cfg(feature = "std")]
+pub use mvcc::{MvccSkipList, Snapshot, SnapshotRange};
+pub use set::{
+    Difference, Intersection, SetIntoIter, SetIter, SetRange, SkipSet, SymmetricDifference, Union,
+};
+#[cfg(feature = "std")]
+pub use sharded::{ShardedIter, ShardedReadGuard, ShardedSkipList};
+pub use skip::{
+    CursorMut, Entry, ExtractIf, IntoIter, Iter, IterMut, OccupiedEntry, Range, RangeMut,
+    SkipList, VacantEntry,
+};
+#[cfg(feature = "std")]
+pub use sorted_set::{SortedSet, ZAddFlags, ZRange};
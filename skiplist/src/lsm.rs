@@ -0,0 +1,552 @@
+mod sstable;
+mod wal;
+
+use crate::skip::{Range, SkipList};
+use core::iter::FusedIterator;
+use core::ops::RangeBounds;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::convert::{TryFrom, TryInto};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub use sstable::{SsTable, SsTableRange};
+use wal::Wal;
+
+// A key and its value, `None` is a tombstone.
+type Record = (Vec<u8>, Option<Vec<u8>>);
+
+/// A source of a `MergeIter`: records sorted by key, `None` values being
+/// tombstones.
+pub type MergeSource<'a> = Box<dyn Iterator<Item = io::Result<(Vec<u8>, Option<Vec<u8>>)>> + 'a>;
+
+// The next record of a source and the index of the source, ordered so
+// the smallest key, then its newest version, is the greatest.
+type Head = Reverse<(Vec<u8>, usize, Option<Vec<u8>>)>;
+
+// Bytes counted for an entry besides its key and value, roughly the size
+// of its skip list node.
+const ENTRY_OVERHEAD: usize = 32;
+
+/// The in-memory table of an LSM tree: a `SkipList` of byte keys and values
+/// with a write-ahead log.
+///
+/// Every write is appended to the log before it is applied, and `open`
+/// replays the log, so the writes survive a crash of the process. Deletes
+/// are kept as tombstones, hiding the older values in SSTables. Once
+/// `size` is large enough, the table is written out by `flush_to_sstable`.
+///
+/// ```
+/// use skiplist::Memtable;
+///
+/// let dir = std::env::temp_dir().join(format!("memtable-doc-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let mut memtable = Memtable::open(dir.join("0.wal")).unwrap();
+/// memtable.put(b"a".to_vec(), b"1".to_vec()).unwrap();
+/// memtable.delete(b"b".to_vec()).unwrap();
+/// assert_eq!(memtable.get(b"a"), Some(Some(&b"1"[..])));
+/// assert_eq!(memtable.get(b"b"), Some(None));
+///
+/// let table = memtable.flush_to_sstable(dir.join("0.sst")).unwrap();
+/// assert_eq!(table.get(b"a").unwrap(), Some(Some(b"1".to_vec())));
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub struct Memtable {
+    map: SkipList<Vec<u8>, Option<Vec<u8>>>,
+    wal: Wal,
+    wal_path: PathBuf,
+    size: usize,
+}
+
+impl Memtable {
+    /// Opens a memtable logging to `wal_path`, replaying the writes already
+    /// in the log. Every record in the log has a CRC, so a write torn or
+    /// left as garbage by a crash at the end of the log is dropped.
+    pub fn open<P: AsRef<Path>>(wal_path: P) -> io::Result<Memtable> {
+        let wal_path = wal_path.as_ref().to_path_buf();
+        let mut map = SkipList::new();
+        let mut size = 0;
+        let wal = Wal::open(&wal_path, |key, value| {
+            size = _apply(&mut map, size, key, value);
+        })?;
+        Ok(Memtable {
+            map,
+            wal,
+            wal_path,
+            size,
+        })
+    }
+
+    /// Sets `key` to `value`.
+    ///
+    /// Fails with `InvalidInput` if the key or the value is 4 GiB or more.
+    pub fn put(&mut self, key: Vec<u8>, value: Vec<u8>) -> io::Result<()> {
+        self._write(key, Some(value))
+    }
+
+    /// Deletes `key` by writing a tombstone.
+    ///
+    /// Fails with `InvalidInput` if the key is 4 GiB or more.
+    pub fn delete(&mut self, key: Vec<u8>) -> io::Result<()> {
+        self._write(key, None)
+    }
+
+    /// Looks up `key`: `None` if the memtable doesn't know it, `Some(None)`
+    /// if it was deleted.
+    pub fn get(&self, key: &[u8]) -> Option<Option<&[u8]>> {
+        self.map.get(key).map(|value| value.as_deref())
+    }
+
+    /// Returns the number of entries, tombstones included.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if the memtable has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Returns the approximate memory used by the entries in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Syncs the log to the disk, so the writes also survive a crash of the
+    /// machine.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.wal.sync()
+    }
+
+    /// Constructs an iterator over a sub-range of entries, tombstones
+    /// included.
+    ///
+    /// Panics if range `start > end` or if range `start == end` and both
+    /// bounds are `Excluded`.
+    pub fn range<R: RangeBounds<[u8]>>(&self, range: R) -> MemtableRange<'_> {
+        MemtableRange {
+            range: self.map.range(range),
+        }
+    }
+
+    /// Writes the entries to an SSTable at `path` and removes the log,
+    /// returning the reader of the new table.
+    ///
+    /// Fails with `InvalidInput` if a data block or the block index is 4 GiB
+    /// or more. The log is kept then.
+    pub fn flush_to_sstable<P: AsRef<Path>>(self, path: P) -> io::Result<SsTable> {
+        let records = self.map.iter().map(|(k, v)| (k.as_slice(), v.as_deref()));
+        sstable::_write(path.as_ref(), records)?;
+        // The table must be found after a crash before the log is gone.
+        _sync_parent(path.as_ref())?;
+        drop(self.wal);
+        fs::remove_file(&self.wal_path)?;
+        SsTable::open(path)
+    }
+
+    /// Constructs an iterator over the live entries inside `range` across
+    /// the memtable and `tables`, which are ordered from the newest to the
+    /// oldest. The newest version of every key wins and deleted keys are
+    /// skipped.
+    ///
+    /// Panics if range `start > end` or if range `start == end` and both
+    /// bounds are `Excluded`.
+    pub fn merged_range<'a, R>(&'a self, tables: &'a [SsTable], range: R) -> MergeIter<'a>
+    where
+        R: RangeBounds<[u8]>,
+    {
+        let bounds = (range.start_bound(), range.end_bound());
+        let mut sources: Vec<MergeSource<'a>> = vec![Box::new(
+            self.range(bounds)
+                .map(|(k, v)| Ok((k.to_vec(), v.map(<[u8]>::to_vec)))),
+        )];
+        for table in tables {
+            sources.push(Box::new(table.range(bounds)));
+        }
+        MergeIter::new(sources)
+    }
+
+    fn _write(&mut self, key: Vec<u8>, value: Option<Vec<u8>>) -> io::Result<()> {
+        self.wal.append(&key, value.as_deref())?;
+        self.size = _apply(&mut self.map, self.size, key, value);
+        Ok(())
+    }
+}
+
+// Applies a write to the map, returning the new size.
+fn _apply(
+    map: &mut SkipList<Vec<u8>, Option<Vec<u8>>>,
+    size: usize,
+    key: Vec<u8>,
+    value: Option<Vec<u8>>,
+) -> usize {
+    let added = value.as_ref().map_or(0, Vec::len);
+    let entry = key.len() + ENTRY_OVERHEAD;
+    match map.insert(key, value) {
+        Some(old) => size + added - old.map_or(0, |v| v.len()),
+        None => size + added + entry,
+    }
+}
+
+/// An iterator over a sub-range of entries in a `Memtable`.
+pub struct MemtableRange<'a> {
+    range: Range<'a, Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl<'a> Iterator for MemtableRange<'a> {
+    type Item = (&'a [u8], Option<&'a [u8]>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.range.next().map(|(k, v)| (k.as_slice(), v.as_deref()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for MemtableRange<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.range
+            .next_back()
+            .map(|(k, v)| (k.as_slice(), v.as_deref()))
+    }
+}
+
+impl<'a> ExactSizeIterator for MemtableRange<'a> {}
+
+impl<'a> FusedIterator for MemtableRange<'a> {}
+
+/// An iterator merging sorted sources, yielding the live entries by key.
+///
+/// Sources are ordered from the newest to the oldest. For a key in several
+/// sources the newest version wins, and keys whose newest version is a
+/// tombstone are skipped. After an error the iterator ends.
+pub struct MergeIter<'a> {
+    sources: Vec<MergeSource<'a>>,
+    heads: BinaryHeap<Head>,
+    started: bool,
+}
+
+impl<'a> MergeIter<'a> {
+    /// Merges `sources`, ordered from the newest to the oldest.
+    pub fn new(sources: Vec<MergeSource<'a>>) -> MergeIter<'a> {
+        MergeIter {
+            heads: BinaryHeap::with_capacity(sources.len()),
+            sources,
+            started: false,
+        }
+    }
+
+    // Pushes the next record of source `i` into the heads.
+    fn _pull(&mut self, i: usize) -> io::Result<()> {
+        if let Some(record) = self.sources[i].next() {
+            let (key, value) = record?;
+            self.heads.push(Reverse((key, i, value)));
+        }
+        Ok(())
+    }
+
+    fn _next(&mut self) -> io::Result<Option<(Vec<u8>, Vec<u8>)>> {
+        if !self.started {
+            self.started = true;
+            for i in 0..self.sources.len() {
+                self._pull(i)?;
+            }
+        }
+        while let Some(Reverse((key, i, value))) = self.heads.pop() {
+            self._pull(i)?;
+            // Older versions of the same key.
+            while self.heads.peek().is_some_and(|Reverse(head)| head.0 == key) {
+                let Reverse((_, j, _)) = self.heads.pop().unwrap();
+                self._pull(j)?;
+            }
+            if let Some(value) = value {
+                return Ok(Some((key, value)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<'a> Iterator for MergeIter<'a> {
+    type Item = io::Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self._next() {
+            Ok(entry) => entry.map(Ok),
+            Err(e) => {
+                self.sources.clear();
+                self.heads.clear();
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+// A record is encoded as
+//   key length u32 | key | 1 and value length u32 | value, or 0 for a tombstone
+// in little endian. Keys and values of 4 GiB or more are rejected.
+fn _encode_record(buf: &mut Vec<u8>, key: &[u8], value: Option<&[u8]>) -> io::Result<()> {
+    _encode_len(buf, key.len())?;
+    buf.extend_from_slice(key);
+    match value {
+        Some(value) => {
+            buf.push(1);
+            _encode_len(buf, value.len())?;
+            buf.extend_from_slice(value);
+        }
+        None => buf.push(0),
+    }
+    Ok(())
+}
+
+fn _encode_len(buf: &mut Vec<u8>, len: usize) -> io::Result<()> {
+    buf.extend_from_slice(&_len_u32(len)?.to_le_bytes());
+    Ok(())
+}
+
+// Lengths are stored as u32, anything longer fails with `InvalidInput`.
+fn _len_u32(len: usize) -> io::Result<u32> {
+    u32::try_from(len).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("length {} doesn't fit in 32 bits", len),
+        )
+    })
+}
+
+// Decodes a record from the front of `buf`, failing with `UnexpectedEof` if
+// it is cut short.
+fn _decode_record(buf: &mut &[u8]) -> io::Result<Record> {
+    let len = _read_u32(buf)? as usize;
+    let key = _read_bytes(buf, len)?.to_vec();
+    let value = match _read_bytes(buf, 1)?[0] {
+        0 => None,
+        1 => {
+            let len = _read_u32(buf)? as usize;
+            Some(_read_bytes(buf, len)?.to_vec())
+        }
+        tag => return Err(_invalid(format!("unknown record tag {}", tag))),
+    };
+    Ok((key, value))
+}
+
+// Syncs the directory of `path`, so a file created in it stays there after
+// a crash. Directories can only be synced like this on Unix.
+fn _sync_parent(path: &Path) -> io::Result<()> {
+    if cfg!(unix) {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        fs::File::open(parent)?.sync_all()?;
+    }
+    Ok(())
+}
+
+fn _read_u32(buf: &mut &[u8]) -> io::Result<u32> {
+    let bytes = _read_bytes(buf, 4)?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn _read_u64(buf: &mut &[u8]) -> io::Result<u64> {
+    let bytes = _read_bytes(buf, 8)?;
+    Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn _read_bytes<'a>(buf: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+    if buf.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let (bytes, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(bytes)
+}
+
+fn _invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+pub(crate) fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("skiplist-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::ops::Bound;
+
+    #[test]
+    fn memtable_wal() {
+        let dir = temp_dir("memtable-wal");
+        let path = dir.join("0.wal");
+        let mut memtable = Memtable::open(&path).unwrap();
+        for i in 0..100u32 {
+            memtable
+                .put(i.to_be_bytes().to_vec(), vec![b'v'; 10])
+                .unwrap();
+        }
+        assert_eq!(memtable.size(), 100 * (4 + 10 + ENTRY_OVERHEAD));
+        memtable.put(7u32.to_be_bytes().to_vec(), vec![]).unwrap();
+        for i in 50..60u32 {
+            memtable.delete(i.to_be_bytes().to_vec()).unwrap();
+        }
+        assert_eq!(memtable.size(), 100 * (4 + ENTRY_OVERHEAD) + 89 * 10);
+        memtable.sync().unwrap();
+        let size = memtable.size();
+        drop(memtable);
+
+        // A torn write at the end of the log.
+        let mut torn = vec![0; 4];
+        _encode_record(&mut torn, b"torn", Some(b"value")).unwrap();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&torn[..torn.len() - 2]).unwrap();
+        drop(file);
+
+        let mut memtable = Memtable::open(&path).unwrap();
+        assert_eq!(memtable.size(), size);
+        assert_eq!(memtable.len(), 100);
+        assert_eq!(memtable.get(&7u32.to_be_bytes()), Some(Some(&b""[..])));
+        assert_eq!(memtable.get(&55u32.to_be_bytes()), Some(None));
+        assert_eq!(memtable.get(b"torn"), None);
+        memtable.put(b"after".to_vec(), b"torn".to_vec()).unwrap();
+        drop(memtable);
+        let memtable = Memtable::open(&path).unwrap();
+        assert_eq!(memtable.get(b"after"), Some(Some(&b"torn"[..])));
+        let start = 48u32.to_be_bytes();
+        let live: Vec<_> = memtable
+            .range((Bound::Included(&start[..]), Bound::Unbounded))
+            .filter(|(_, v)| v.is_some())
+            .take(3)
+            .map(|(k, _)| k.to_vec())
+            .collect();
+        assert_eq!(
+            live,
+            vec![
+                48u32.to_be_bytes().to_vec(),
+                49u32.to_be_bytes().to_vec(),
+                60u32.to_be_bytes().to_vec()
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn record_length_limit() {
+        let mut buf = vec![];
+        _encode_len(&mut buf, u32::MAX as usize).unwrap();
+        assert_eq!(buf, vec![0xff; 4]);
+        let err = _encode_len(&mut buf, u32::MAX as usize + 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(buf.len(), 4);
+    }
+
+    #[test]
+    fn wal_corrupt_tail() {
+        let dir = temp_dir("wal-corrupt-tail");
+        let path = dir.join("0.wal");
+        let mut memtable = Memtable::open(&path).unwrap();
+        memtable.put(b"a".to_vec(), b"1".to_vec()).unwrap();
+        memtable.delete(b"b".to_vec()).unwrap();
+        drop(memtable);
+        let valid = fs::metadata(&path).unwrap().len();
+
+        // Zeros would decode as tombstones of the empty key, and garbage as
+        // anything, without the CRC.
+        let garbage: Vec<u8> = (0..200u32).map(|i| (i * 7 + 3) as u8).collect();
+        let mut flipped = vec![];
+        let mut record = vec![];
+        _encode_record(&mut record, b"c", Some(b"3")).unwrap();
+        flipped.extend_from_slice(&crc32fast::hash(&record).to_le_bytes());
+        flipped.extend_from_slice(&record);
+        *flipped.last_mut().unwrap() ^= 1;
+        for tail in [vec![0; 64], garbage, flipped].iter() {
+            let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+            file.write_all(tail).unwrap();
+            drop(file);
+
+            let memtable = Memtable::open(&path).unwrap();
+            assert_eq!(memtable.len(), 2);
+            assert_eq!(memtable.get(b""), None);
+            assert_eq!(memtable.get(b"c"), None);
+            assert_eq!(fs::metadata(&path).unwrap().len(), valid);
+        }
+
+        let mut memtable = Memtable::open(&path).unwrap();
+        memtable.put(b"d".to_vec(), b"4".to_vec()).unwrap();
+        drop(memtable);
+        let memtable = Memtable::open(&path).unwrap();
+        assert_eq!(memtable.get(b"d"), Some(Some(&b"4"[..])));
+        assert_eq!(memtable.get(b"a"), Some(Some(&b"1"[..])));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn merged_range() {
+        let dir = temp_dir("merged-range");
+        let key = |i: u32| format!("key{:05}", i).into_bytes();
+        let mut tables = vec![];
+        // Table `t` sets the keys divisible by `t + 1` to `t`, and deletes
+        // the keys which are 1 more.
+        for t in 0..3u32 {
+            let mut memtable = Memtable::open(dir.join(format!("{}.wal", t))).unwrap();
+            for i in (0..3000).step_by(t as usize + 1) {
+                memtable.put(key(i), t.to_string().into_bytes()).unwrap();
+                memtable.delete(key(i + 1)).unwrap();
+            }
+            tables.insert(
+                0,
+                memtable
+                    .flush_to_sstable(dir.join(format!("{}.sst", t)))
+                    .unwrap(),
+            );
+            assert!(!dir.join(format!("{}.wal", t)).exists());
+        }
+        let mut memtable = Memtable::open(dir.join("3.wal")).unwrap();
+        memtable.put(key(1), b"m".to_vec()).unwrap();
+        memtable.delete(key(0)).unwrap();
+
+        // The newest write of every key.
+        let mut expected = std::collections::BTreeMap::new();
+        for t in 0..3u32 {
+            for i in (0..3000).step_by(t as usize + 1) {
+                expected.insert(key(i), Some(t.to_string().into_bytes()));
+                expected.insert(key(i + 1), None);
+            }
+        }
+        expected.insert(key(1), Some(b"m".to_vec()));
+        expected.insert(key(0), None);
+
+        let merged: Vec<_> = memtable
+            .merged_range(&tables, ..)
+            .map(Result::unwrap)
+            .collect();
+        let live: Vec<_> = expected
+            .iter()
+            .filter_map(|(k, v)| Some((k.clone(), v.clone()?)))
+            .collect();
+        assert_eq!(merged, live);
+
+        let (start, end) = (key(100), key(200));
+        let bounds = (Bound::Excluded(&start[..]), Bound::Included(&end[..]));
+        let merged: Vec<_> = memtable
+            .merged_range(&tables, bounds)
+            .map(Result::unwrap)
+            .collect();
+        let live: Vec<_> = live
+            .into_iter()
+            .filter(|(k, _)| *k > start && *k <= end)
+            .collect();
+        assert_eq!(merged, live);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
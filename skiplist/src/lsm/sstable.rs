@@ -0,0 +1,350 @@
+use super::{
+    _decode_record, _encode_record, _invalid, _len_u32, _read_bytes, _read_u32, _read_u64, Record,
+};
+use crate::comparator::OrdComparator;
+use crate::skip::_check_range;
+use core::ops::{Bound, RangeBounds};
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// An SSTable file is
+//   data blocks | index | footer
+// A data block holds encoded records sorted by key, and is cut once it
+// reaches `BLOCK_SIZE`. The index has an entry for every block:
+//   first key length u32 | first key | offset u64 | length u32
+// The footer is
+//   index offset u64 | index length u32 | number of records u64 | MAGIC
+const BLOCK_SIZE: usize = 4096;
+const MAGIC: u64 = 0x5353_5441_424c_4531;
+const FOOTER_SIZE: usize = 8 + 4 + 8 + 8;
+
+// Where a data block is in the file.
+struct BlockHandle {
+    first_key: Vec<u8>,
+    offset: u64,
+    len: u32,
+}
+
+// Writes `records`, sorted by key, to a new SSTable at `path` and syncs it.
+pub(super) fn _write<'a, I>(path: &Path, records: I) -> io::Result<()>
+where
+    I: Iterator<Item = (&'a [u8], Option<&'a [u8]>)>,
+{
+    let mut file = BufWriter::new(File::create(path)?);
+    let mut index = vec![];
+    let mut block = vec![];
+    let mut first_key = vec![];
+    let mut offset = 0;
+    let mut len = 0u64;
+    let mut cut =
+        |file: &mut BufWriter<File>, block: &mut Vec<u8>, first_key: &[u8]| -> io::Result<()> {
+            // A block is cut after the record that fills it, which can be
+            // up to two 4 GiB fields long.
+            let len = _len_u32(block.len())?;
+            file.write_all(block)?;
+            index.push(BlockHandle {
+                first_key: first_key.to_vec(),
+                offset,
+                len,
+            });
+            offset += block.len() as u64;
+            block.clear();
+            Ok(())
+        };
+    for (key, value) in records {
+        if block.is_empty() {
+            first_key = key.to_vec();
+        }
+        _encode_record(&mut block, key, value)?;
+        len += 1;
+        if block.len() >= BLOCK_SIZE {
+            cut(&mut file, &mut block, &first_key)?;
+        }
+    }
+    if !block.is_empty() {
+        cut(&mut file, &mut block, &first_key)?;
+    }
+
+    let index_offset = offset;
+    let mut tail = vec![];
+    for handle in &index {
+        tail.extend_from_slice(&_len_u32(handle.first_key.len())?.to_le_bytes());
+        tail.extend_from_slice(&handle.first_key);
+        tail.extend_from_slice(&handle.offset.to_le_bytes());
+        tail.extend_from_slice(&handle.len.to_le_bytes());
+    }
+    let index_len = _len_u32(tail.len())?;
+    tail.extend_from_slice(&index_offset.to_le_bytes());
+    tail.extend_from_slice(&index_len.to_le_bytes());
+    tail.extend_from_slice(&len.to_le_bytes());
+    tail.extend_from_slice(&MAGIC.to_le_bytes());
+    file.write_all(&tail)?;
+    file.into_inner()
+        .map_err(io::IntoInnerError::into_error)?
+        .sync_all()
+}
+
+/// A reader of an immutable, sorted SSTable file written by
+/// `Memtable::flush_to_sstable`.
+///
+/// The block index is kept in memory, so a lookup reads a single block.
+pub struct SsTable {
+    file: Mutex<File>,
+    path: PathBuf,
+    index: Vec<BlockHandle>,
+    len: usize,
+}
+
+impl SsTable {
+    /// Opens the SSTable at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<SsTable> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = File::open(&path)?;
+        let size = file.seek(SeekFrom::End(0))?;
+        if size < FOOTER_SIZE as u64 {
+            return Err(_invalid(format!(
+                "{} is too short for an SSTable",
+                path.display()
+            )));
+        }
+        let mut footer = [0; FOOTER_SIZE];
+        file.seek(SeekFrom::End(-(FOOTER_SIZE as i64)))?;
+        file.read_exact(&mut footer)?;
+        let mut rest = &footer[..];
+        let index_offset = _read_u64(&mut rest)?;
+        let index_len = _read_u32(&mut rest)?;
+        let len = _read_u64(&mut rest)? as usize;
+        if _read_u64(&mut rest)? != MAGIC {
+            return Err(_invalid(format!("{} is not an SSTable", path.display())));
+        }
+
+        let mut data = vec![0; index_len as usize];
+        file.seek(SeekFrom::Start(index_offset))?;
+        file.read_exact(&mut data)?;
+        let mut rest = &data[..];
+        let mut index = vec![];
+        while !rest.is_empty() {
+            let key_len = _read_u32(&mut rest)? as usize;
+            let first_key = _read_bytes(&mut rest, key_len)?.to_vec();
+            let offset = _read_u64(&mut rest)?;
+            let len = _read_u32(&mut rest)?;
+            index.push(BlockHandle {
+                first_key,
+                offset,
+                len,
+            });
+        }
+        Ok(SsTable {
+            file: Mutex::new(file),
+            path,
+            index,
+            len,
+        })
+    }
+
+    /// Returns the path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the number of records, tombstones included.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the table has no records.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Looks up `key`: `None` if the table doesn't have it, `Some(None)` if
+    /// it has a tombstone.
+    pub fn get(&self, key: &[u8]) -> io::Result<Option<Option<Vec<u8>>>> {
+        let block = match self._block_of(key) {
+            Some(block) => self._read_block(block)?,
+            None => return Ok(None),
+        };
+        let found = block.into_iter().find(|(k, _)| k.as_slice() == key);
+        Ok(found.map(|(_, value)| value))
+    }
+
+    /// Constructs an iterator over a sub-range of records, tombstones
+    /// included.
+    ///
+    /// Panics if range `start > end` or if range `start == end` and both
+    /// bounds are `Excluded`.
+    pub fn range<R: RangeBounds<[u8]>>(&self, range: R) -> SsTableRange<'_> {
+        _check_range(&range, &OrdComparator);
+        let block = match range.start_bound() {
+            Bound::Included(key) | Bound::Excluded(key) => self._block_of(key).unwrap_or(0),
+            Bound::Unbounded => 0,
+        };
+        SsTableRange {
+            table: self,
+            block,
+            records: vec![].into_iter(),
+            start: _owned(range.start_bound()),
+            end: _owned(range.end_bound()),
+        }
+    }
+
+    /// Constructs an iterator over all records.
+    pub fn iter(&self) -> SsTableRange<'_> {
+        self.range::<core::ops::RangeFull>(..)
+    }
+
+    // The index of the last block whose first key is not greater than
+    // `key`, the only block which may hold it.
+    fn _block_of(&self, key: &[u8]) -> Option<usize> {
+        let after = self
+            .index
+            .partition_point(|handle| handle.first_key.as_slice() <= key);
+        after.checked_sub(1)
+    }
+
+    fn _read_block(&self, block: usize) -> io::Result<Vec<Record>> {
+        let handle = &self.index[block];
+        let mut data = vec![0; handle.len as usize];
+        {
+            let mut file = self.file.lock().unwrap();
+            file.seek(SeekFrom::Start(handle.offset))?;
+            file.read_exact(&mut data)?;
+        }
+        let mut rest = &data[..];
+        let mut records = vec![];
+        while !rest.is_empty() {
+            records.push(_decode_record(&mut rest)?);
+        }
+        Ok(records)
+    }
+}
+
+fn _owned(bound: Bound<&[u8]>) -> Bound<Vec<u8>> {
+    match bound {
+        Bound::Included(key) => Bound::Included(key.to_vec()),
+        Bound::Excluded(key) => Bound::Excluded(key.to_vec()),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+/// An iterator over a sub-range of records in an `SsTable`, reading a
+/// block at a time. After an error the iterator ends.
+pub struct SsTableRange<'a> {
+    table: &'a SsTable,
+    // The next block to read.
+    block: usize,
+    records: std::vec::IntoIter<Record>,
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+}
+
+impl<'a> Iterator for SsTableRange<'a> {
+    type Item = io::Result<(Vec<u8>, Option<Vec<u8>>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((key, value)) = self.records.next() {
+                let before = match &self.start {
+                    Bound::Included(start) => key < *start,
+                    Bound::Excluded(start) => key <= *start,
+                    Bound::Unbounded => false,
+                };
+                if before {
+                    continue;
+                }
+                let after = match &self.end {
+                    Bound::Included(end) => key > *end,
+                    Bound::Excluded(end) => key >= *end,
+                    Bound::Unbounded => false,
+                };
+                if after {
+                    self.block = self.table.index.len();
+                    self.records = vec![].into_iter();
+                    return None;
+                }
+                return Some(Ok((key, value)));
+            }
+            if self.block >= self.table.index.len() {
+                return None;
+            }
+            let block = self.block;
+            self.block += 1;
+            match self.table._read_block(block) {
+                Ok(records) => self.records = records.into_iter(),
+                Err(e) => {
+                    self.block = self.table.index.len();
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::temp_dir;
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn get_and_range() {
+        let dir = temp_dir("sstable");
+        let path = dir.join("0.sst");
+        let key = |i: u32| format!("{:06}", i).into_bytes();
+        let value = |i: u32| match i % 7 {
+            0 => None,
+            _ => Some(vec![i as u8; i as usize % 100]),
+        };
+        let records: Vec<_> = (0..20000).step_by(2).map(|i| (key(i), value(i))).collect();
+        _write(
+            &path,
+            records.iter().map(|(k, v)| (k.as_slice(), v.as_deref())),
+        )
+        .unwrap();
+
+        let table = SsTable::open(&path).unwrap();
+        assert_eq!(table.len(), 10000);
+        assert!(table.index.len() > 100);
+        for i in (0..20001).step_by(7) {
+            let expected = if i % 2 == 0 && i < 20000 {
+                Some(value(i))
+            } else {
+                None
+            };
+            assert_eq!(table.get(&key(i)).unwrap(), expected);
+        }
+        assert_eq!(table.get(b"").unwrap(), None);
+        assert_eq!(table.get(b"999999").unwrap(), None);
+
+        assert!(table.iter().map(Result::unwrap).eq(records.iter().cloned()));
+        let (start, end) = (key(5001), key(9000));
+        let range = table.range((Bound::Excluded(&start[..]), Bound::Excluded(&end[..])));
+        let expected = records
+            .iter()
+            .filter(|(k, _)| *k > start && *k < end)
+            .cloned();
+        assert!(range.map(Result::unwrap).eq(expected));
+        assert_eq!(
+            table
+                .range((Bound::Included(&b"a"[..]), Bound::Unbounded))
+                .count(),
+            0
+        );
+
+        let empty = dir.join("empty.sst");
+        _write(&empty, std::iter::empty()).unwrap();
+        let table = SsTable::open(&empty).unwrap();
+        assert!(table.is_empty());
+        assert_eq!(table.get(b"a").unwrap(), None);
+        assert_eq!(table.iter().count(), 0);
+
+        fs::write(&empty, b"not an sstable, but long enough").unwrap();
+        assert_eq!(
+            SsTable::open(&empty).err().unwrap().kind(),
+            io::ErrorKind::InvalidData
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,71 @@
+use super::{_decode_record, _encode_record, _read_u32};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+// The write-ahead log of a memtable, a sequence of encoded records each
+// preceded by its CRC32 as a little endian u32.
+pub(super) struct Wal {
+    file: File,
+    buf: Vec<u8>,
+}
+
+impl Wal {
+    // Opens the log at `path`, passing every record in it to `replay`. The
+    // log is cut off at the first record which is torn or doesn't match its
+    // CRC, like the zeros or garbage left by a crash in the middle of a
+    // write.
+    pub(super) fn open<F>(path: &Path, mut replay: F) -> io::Result<Wal>
+    where
+        F: FnMut(Vec<u8>, Option<Vec<u8>>),
+    {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let mut data = vec![];
+        file.read_to_end(&mut data)?;
+
+        let mut rest = &data[..];
+        let mut valid = 0;
+        while let Some((key, value)) = _next_record(&mut rest) {
+            replay(key, value);
+            valid = data.len() - rest.len();
+        }
+        if valid < data.len() {
+            file.set_len(valid as u64)?;
+        }
+        file.seek(SeekFrom::Start(valid as u64))?;
+        Ok(Wal { file, buf: vec![] })
+    }
+
+    // Appends a record with a single write, so it reaches the OS before
+    // the memtable changes.
+    pub(super) fn append(&mut self, key: &[u8], value: Option<&[u8]>) -> io::Result<()> {
+        self.buf.clear();
+        self.buf.extend_from_slice(&[0; 4]);
+        _encode_record(&mut self.buf, key, value)?;
+        let crc = crc32fast::hash(&self.buf[4..]);
+        self.buf[..4].copy_from_slice(&crc.to_le_bytes());
+        self.file.write_all(&self.buf)
+    }
+
+    pub(super) fn sync(&mut self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+}
+
+// Decodes the next record if it is whole and matches its CRC.
+fn _next_record(rest: &mut &[u8]) -> Option<(Vec<u8>, Option<Vec<u8>>)> {
+    let mut buf = *rest;
+    let crc = _read_u32(&mut buf).ok()?;
+    let encoded = buf;
+    let record = _decode_record(&mut buf).ok()?;
+    if crc32fast::hash(&encoded[..encoded.len() - buf.len()]) != crc {
+        return None;
+    }
+    *rest = buf;
+    Some(record)
+}
@@ -0,0 +1,429 @@
+use crate::level::LevelGenerator;
+use crate::skip::{Iter, Range, SkipList};
+use alloc::vec::Vec;
+use alloc::collections::vec_deque::{self, VecDeque};
+use core::borrow::Borrow;
+use core::iter::FromIterator;
+use core::iter::FusedIterator;
+use core::ops::RangeBounds;
+
+/// An ordered map allowing duplicate keys, a `SkipList` whose values are
+/// the queues of values inserted under each key.
+///
+/// Values with equal keys are kept in insertion order.
+///
+/// ```
+/// use skiplist::SkipMultiMap;
+///
+/// let mut events = SkipMultiMap::new();
+/// events.insert(2, "b");
+/// events.insert(1, "a");
+/// events.insert(2, "c");
+/// assert!(events.get_all(&2).eq(&["b", "c"]));
+/// assert_eq!(events.remove_one(&2), Some("b"));
+/// assert!(events.iter().eq(vec![(&1, &"a"), (&2, &"c")]));
+/// ```
+pub struct SkipMultiMap<K, V> {
+    map: SkipList<K, VecDeque<V>>,
+    // Number of values, `map.len()` is the number of distinct keys.
+    len: usize,
+}
+
+impl<K, V> SkipMultiMap<K, V> {
+    pub fn new() -> SkipMultiMap<K, V> {
+        SkipMultiMap {
+            map: SkipList::new(),
+            len: 0,
+        }
+    }
+
+    /// Creates an empty map whose tower heights are chosen by
+    /// `level_generator`.
+    pub fn with_level_generator<G>(level_generator: G) -> SkipMultiMap<K, V>
+    where
+        G: LevelGenerator + 'static,
+    {
+        SkipMultiMap {
+            map: SkipList::with_level_generator(level_generator),
+            len: 0,
+        }
+    }
+
+    /// Returns the number of values in the map, duplicates included.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the number of distinct keys in the map.
+    pub fn keys_len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if the map contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Clears the map, removing all values.
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.len = 0;
+    }
+
+    /// Visits every entry in key order, duplicates in insertion order.
+    pub fn iter(&self) -> MultiIter<'_, K, V> {
+        MultiIter {
+            inner: Flatten::new(self.map.iter()),
+            len: self.len,
+        }
+    }
+}
+
+impl<K: Ord, V> SkipMultiMap<K, V> {
+    /// Adds a value under `key`, after the values already there.
+    pub fn insert(&mut self, key: K, value: V) {
+        self.map.entry(key).or_default().push_back(value);
+        self.len += 1;
+    }
+
+    /// Visits the values under `q` in insertion order.
+    pub fn get_all<Q>(&self, q: &Q) -> GetAll<'_, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        GetAll {
+            iter: self.map.get(q).map(|values| values.iter()),
+        }
+    }
+
+    /// Returns the number of values under `q`.
+    pub fn count<Q>(&self, q: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.get(q).map_or(0, |values| values.len())
+    }
+
+    /// Returns `true` if the map contains a value under `q`.
+    pub fn contains_key<Q>(&self, q: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.contains_key(q)
+    }
+
+    /// Removes and returns the earliest inserted value under `q`.
+    pub fn remove_one<Q>(&mut self, q: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut cursor = self.map.lower_bound_cursor(q);
+        match cursor.key() {
+            Some(key) if key.borrow() == q => (),
+            _ => return None,
+        }
+        let values = cursor.value_mut()?;
+        let value = values.pop_front();
+        if values.is_empty() {
+            cursor.remove_current();
+        }
+        self.len -= 1;
+        value
+    }
+
+    /// Removes every value under `q` and returns them in insertion order.
+    pub fn remove_all<Q>(&mut self, q: &Q) -> Vec<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let values = Vec::from(self.map.remove(q).unwrap_or_default());
+        self.len -= values.len();
+        values
+    }
+
+    /// Constructs a double-ended iterator over the entries whose keys are
+    /// inside `range`, every duplicate included.
+    ///
+    /// Panics if range `start > end` or if range `start == end` and both
+    /// bounds are `Excluded`.
+    pub fn range<Q, R>(&self, range: R) -> MultiRange<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let keys = self.map.range(range);
+        // The values are counted up front, a pass over the keys only.
+        let len = keys.clone().map(|(_, values)| values.len()).sum();
+        MultiRange {
+            inner: Flatten::new(keys),
+            len,
+        }
+    }
+}
+
+impl<K, V> Default for SkipMultiMap<K, V> {
+    fn default() -> SkipMultiMap<K, V> {
+        SkipMultiMap::new()
+    }
+}
+
+impl<K: Ord, V> FromIterator<(K, V)> for SkipMultiMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> SkipMultiMap<K, V> {
+        let mut map = SkipMultiMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: Ord, V> Extend<(K, V)> for SkipMultiMap<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a SkipMultiMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = MultiIter<'a, K, V>;
+
+    fn into_iter(self) -> MultiIter<'a, K, V> {
+        self.iter()
+    }
+}
+
+/// An iterator over the values under one key of a `SkipMultiMap`.
+pub struct GetAll<'a, V> {
+    iter: Option<vec_deque::Iter<'a, V>>,
+}
+
+impl<'a, V> Iterator for GetAll<'a, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.as_mut()?.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.iter.as_ref().map_or(0, |iter| iter.len());
+        (len, Some(len))
+    }
+}
+
+impl<'a, V> DoubleEndedIterator for GetAll<'a, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.as_mut()?.next_back()
+    }
+}
+
+impl<'a, V> ExactSizeIterator for GetAll<'a, V> {}
+
+impl<'a, V> FusedIterator for GetAll<'a, V> {}
+
+// Flattens the queues of an iterator over the `SkipList` into entries, from
+// both ends.
+struct Flatten<'a, K, V, I> {
+    iter: I,
+    front: Option<(&'a K, vec_deque::Iter<'a, V>)>,
+    back: Option<(&'a K, vec_deque::Iter<'a, V>)>,
+}
+
+impl<'a, K, V, I> Flatten<'a, K, V, I>
+where
+    I: DoubleEndedIterator<Item = (&'a K, &'a VecDeque<V>)>,
+{
+    fn new(iter: I) -> Flatten<'a, K, V, I> {
+        Flatten {
+            iter,
+            front: None,
+            back: None,
+        }
+    }
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            if let Some((k, values)) = &mut self.front {
+                if let Some(v) = values.next() {
+                    return Some((*k, v));
+                }
+            }
+            match self.iter.next() {
+                Some((k, values)) => self.front = Some((k, values.iter())),
+                None => {
+                    // The rest is in the queue taken by the back.
+                    let (k, values) = self.back.as_mut()?;
+                    return values.next().map(|v| (*k, v));
+                }
+            }
+        }
+    }
+
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            if let Some((k, values)) = &mut self.back {
+                if let Some(v) = values.next_back() {
+                    return Some((*k, v));
+                }
+            }
+            match self.iter.next_back() {
+                Some((k, values)) => self.back = Some((k, values.iter())),
+                None => {
+                    let (k, values) = self.front.as_mut()?;
+                    return values.next_back().map(|v| (*k, v));
+                }
+            }
+        }
+    }
+}
+
+/// An iterator over the entries of a `SkipMultiMap`.
+pub struct MultiIter<'a, K, V> {
+    inner: Flatten<'a, K, V, Iter<'a, K, VecDeque<V>>>,
+    len: usize,
+}
+
+impl<'a, K, V> Iterator for MultiIter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.inner.next()?;
+        self.len -= 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for MultiIter<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let item = self.inner.next_back()?;
+        self.len -= 1;
+        Some(item)
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for MultiIter<'a, K, V> {}
+
+impl<'a, K, V> FusedIterator for MultiIter<'a, K, V> {}
+
+/// An iterator over a sub-range of entries in a `SkipMultiMap`.
+pub struct MultiRange<'a, K, V> {
+    inner: Flatten<'a, K, V, Range<'a, K, VecDeque<V>>>,
+    len: usize,
+}
+
+impl<'a, K, V> Iterator for MultiRange<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.inner.next()?;
+        self.len -= 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for MultiRange<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let item = self.inner.next_back()?;
+        self.len -= 1;
+        Some(item)
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for MultiRange<'a, K, V> {}
+
+impl<'a, K, V> FusedIterator for MultiRange<'a, K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeMap;
+    use std::vec;
+
+    #[test]
+    fn multimap() {
+        let mut mm: SkipMultiMap<i32, &str> =
+            vec![(1, "a"), (2, "b"), (1, "c"), (3, "d"), (2, "e"), (1, "f")].into_iter().collect();
+        assert_eq!(mm.len(), 6);
+        assert_eq!(mm.keys_len(), 3);
+        assert_eq!(mm.count(&1), 3);
+        assert!(mm.get_all(&1).eq(&["a", "c", "f"]));
+        assert!(mm.get_all(&1).rev().eq(&["f", "c", "a"]));
+        assert_eq!(mm.get_all(&4).next(), None);
+        let all = [(1, "a"), (1, "c"), (1, "f"), (2, "b"), (2, "e"), (3, "d")];
+        assert!(mm.iter().map(|(k, v)| (*k, *v)).eq(all.iter().cloned()));
+        assert!(mm.iter().rev().map(|(k, v)| (*k, *v)).eq(all.iter().rev().cloned()));
+        assert!(mm.range(2..).map(|(_, v)| *v).eq(vec!["b", "e", "d"]));
+
+        let mut range = mm.range(..=2);
+        assert_eq!(range.len(), 5);
+        assert_eq!(range.next(), Some((&1, &"a")));
+        assert_eq!(range.next_back(), Some((&2, &"e")));
+        assert_eq!(range.next_back(), Some((&2, &"b")));
+        assert_eq!(range.next_back(), Some((&1, &"f")));
+        assert_eq!(range.next(), Some((&1, &"c")));
+        assert_eq!(range.len(), 0);
+        assert_eq!(range.next(), None);
+        assert_eq!(range.next_back(), None);
+
+        assert_eq!(mm.remove_one(&1), Some("a"));
+        assert_eq!(mm.remove_one(&3), Some("d"));
+        assert_eq!(mm.remove_one(&3), None);
+        assert!(!mm.contains_key(&3));
+        assert_eq!(mm.remove_all(&1), vec!["c", "f"]);
+        assert_eq!(mm.remove_all(&1), Vec::<&str>::new());
+        assert_eq!(mm.len(), 2);
+        assert_eq!(mm.keys_len(), 1);
+        mm.clear();
+        assert!(mm.is_empty());
+    }
+
+    #[test]
+    fn same_as_btreemap() {
+        let mut rng = StdRng::seed_from_u64(16);
+        let mut mm = SkipMultiMap::new();
+        let mut bt: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+        for i in 0..3000 {
+            let k = rng.gen_range(0, 100);
+            match rng.gen_range(0, 5) {
+                0 => {
+                    let expected = bt.get_mut(&k).map(|values| values.remove(0));
+                    if bt.get(&k).is_some_and(|values| values.is_empty()) {
+                        bt.remove(&k);
+                    }
+                    assert_eq!(mm.remove_one(&k), expected);
+                }
+                1 => assert_eq!(mm.remove_all(&k), bt.remove(&k).unwrap_or_default()),
+                _ => {
+                    mm.insert(k, i);
+                    bt.entry(k).or_default().push(i);
+                }
+            }
+        }
+        let flat = |bt: &BTreeMap<u32, Vec<u32>>| {
+            bt.iter()
+                .flat_map(|(k, values)| values.iter().map(move |v| (*k, *v)))
+                .collect::<Vec<_>>()
+        };
+        let all = flat(&bt);
+        assert_eq!(mm.len(), all.len());
+        assert!(mm.iter().map(|(k, v)| (*k, *v)).eq(all.iter().cloned()));
+        let part: BTreeMap<_, _> = bt.range(20..50).map(|(k, v)| (*k, v.clone())).collect();
+        assert!(mm.range(20..50).rev().map(|(k, v)| (*k, *v)).eq(flat(&part).into_iter().rev()));
+        assert_eq!(mm.range(20..50).len(), flat(&part).len());
+    }
+}
@@ -0,0 +1,387 @@
+//! Snapshot reads for an ordered map.
+//!
+//! There is no `SkipList::snapshot`: writes to a `SkipList` need `&mut self`,
+//! so they can't run while a snapshot borrows the list.
+
+use crate::concurrent::{ConcurrentRange, ConcurrentSkipList};
+use std::cmp::Reverse;
+use std::ops::{Bound, RangeBounds};
+use std::sync::atomic::{AtomicU64, Ordering as MemOrdering};
+use std::sync::Mutex;
+
+// A key and the sequence number of one of its versions. Versions of the same
+// key are sorted from the newest to the oldest.
+type Version<K> = (K, Reverse<u64>);
+
+type VersionRange<'a, K, V> =
+    ConcurrentRange<'a, Version<K>, (Bound<Version<K>>, Bound<Version<K>>), Version<K>, Option<V>>;
+
+/// An ordered map keeping versions of its entries, so readers can take a
+/// point-in-time `Snapshot` while a writer keeps modifying the map.
+///
+/// Every write gets the next sequence number and adds a version, a removal
+/// adds a tombstone. A snapshot reads the newest version of every key not
+/// newer than its sequence number. The versions live in a
+/// `ConcurrentSkipList`, so reads never wait for writes. Versions no
+/// snapshot can see anymore are dropped by `gc`.
+///
+/// ```
+/// use skiplist::MvccSkipList;
+///
+/// let sk = MvccSkipList::new();
+/// sk.insert(1, "a");
+/// let snapshot = sk.snapshot();
+/// assert_eq!(sk.insert(1, "b"), Some("a"));
+/// sk.insert(2, "c");
+/// assert_eq!(snapshot.get(&1), Some("a"));
+/// assert_eq!(snapshot.get(&2), None);
+/// assert_eq!(sk.get(&1), Some("b"));
+/// ```
+pub struct MvccSkipList<K, V> {
+    // A `None` value is a tombstone.
+    versions: ConcurrentSkipList<Version<K>, Option<V>>,
+    // The newest sequence number visible to new snapshots.
+    seq: AtomicU64,
+    // Writes are serialized so sequence numbers are published in order.
+    write: Mutex<()>,
+    // Sequence numbers and ids of live snapshots.
+    snapshots: ConcurrentSkipList<(u64, u64), ()>,
+    // The next snapshot id.
+    ids: AtomicU64,
+    // No snapshot older than this is protected from a running `gc`.
+    horizon: AtomicU64,
+}
+
+impl<K, V> MvccSkipList<K, V> {
+    pub fn new() -> MvccSkipList<K, V> {
+        MvccSkipList {
+            versions: ConcurrentSkipList::new(),
+            seq: AtomicU64::new(0),
+            write: Mutex::new(()),
+            snapshots: ConcurrentSkipList::new(),
+            ids: AtomicU64::new(0),
+            horizon: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the sequence number of the last write.
+    pub fn seq(&self) -> u64 {
+        self.seq.load(MemOrdering::SeqCst)
+    }
+
+    /// Returns a read-only view of the map as of now.
+    ///
+    /// Taking and dropping a snapshot doesn't lock.
+    pub fn snapshot(&self) -> Snapshot<'_, K, V> {
+        let id = self.ids.fetch_add(1, MemOrdering::Relaxed);
+        loop {
+            let seq = self.seq();
+            self.snapshots.insert((seq, id), ());
+            // A `gc` which started before the registration may have missed
+            // it, and dropped versions the snapshot needs if `seq` is older
+            // than what that `gc` saw. Try again with a newer `seq`.
+            if self.horizon.load(MemOrdering::SeqCst) <= seq {
+                return Snapshot { list: self, seq, id };
+            }
+            self.snapshots.remove(&(seq, id));
+        }
+    }
+}
+
+impl<K, V> MvccSkipList<K, V>
+where
+    K: Ord + Clone + Send + 'static,
+    V: Clone + Send + 'static,
+{
+    /// Inserts a new version of `key`, returning the newest value before
+    /// the insertion.
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        self._write(key, Some(value))
+    }
+
+    /// Removes `key` from the map, returning its value before the removal.
+    /// Snapshots taken before still see the key.
+    pub fn remove(&self, key: &K) -> Option<V> {
+        self._write(key.clone(), None)
+    }
+
+    /// Returns the newest value of `key`.
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.snapshot().get(key)
+    }
+
+    /// Drops the versions which no live snapshot, and no snapshot taken
+    /// later, can see. Returns the number of versions dropped.
+    ///
+    /// It runs alongside readers and writers.
+    pub fn gc(&self) -> usize {
+        // Snapshots registered after the horizon is raised either show up
+        // below or see the new horizon and retry.
+        let seq = self.seq();
+        self.horizon.fetch_max(seq, MemOrdering::SeqCst);
+        let oldest = match self.snapshots.iter().next() {
+            Some(((oldest, _), _)) => oldest.min(seq),
+            None => seq,
+        };
+
+        // For every key, the newest version not newer than `oldest` is kept
+        // and the older ones are garbage. If the kept one is a tombstone it
+        // goes too, after the older versions so no reader sees them again.
+        let mut garbage = vec![];
+        let mut tombstone = None;
+        let mut last: Option<K> = None;
+        let mut found = false;
+        for ((key, Reverse(seq)), value) in self.versions.iter() {
+            if last.as_ref() != Some(&key) {
+                garbage.extend(tombstone.take());
+                last = Some(key.clone());
+                found = false;
+            }
+            if seq > oldest {
+                continue;
+            }
+            if found {
+                garbage.push((key, Reverse(seq)));
+            } else {
+                found = true;
+                if value.is_none() {
+                    tombstone = Some((key, Reverse(seq)));
+                }
+            }
+        }
+        garbage.extend(tombstone);
+
+        for version in &garbage {
+            self.versions.remove(version);
+        }
+        garbage.len()
+    }
+
+    // A `None` value removes `key`, nothing is written if it's not there.
+    fn _write(&self, key: K, value: Option<V>) -> Option<V> {
+        let _write = self.write.lock().unwrap();
+        let old = self.snapshot().get(&key);
+        if old.is_none() && value.is_none() {
+            return None;
+        }
+        let seq = self.seq() + 1;
+        self.versions.insert((key, Reverse(seq)), value);
+        self.seq.store(seq, MemOrdering::SeqCst);
+        old
+    }
+}
+
+impl<K, V> Default for MvccSkipList<K, V> {
+    fn default() -> MvccSkipList<K, V> {
+        MvccSkipList::new()
+    }
+}
+
+/// A read-only, point-in-time view of an `MvccSkipList`.
+///
+/// Versions the snapshot can see are kept by `gc` until it is dropped.
+pub struct Snapshot<'a, K, V> {
+    list: &'a MvccSkipList<K, V>,
+    seq: u64,
+    id: u64,
+}
+
+impl<'a, K, V> Snapshot<'a, K, V> {
+    /// Returns the sequence number of the last write the snapshot sees.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+}
+
+impl<'a, K, V> Snapshot<'a, K, V>
+where
+    K: Ord + Clone + Send + 'static,
+    V: Clone + Send + 'static,
+{
+    /// Returns the value of `key` as of the snapshot.
+    pub fn get(&self, key: &K) -> Option<V> {
+        // The first version not newer than the snapshot.
+        let from = (key.clone(), Reverse(self.seq));
+        let ((found, _), value) = self.list.versions.range(from..).next()?;
+        if found == *key {
+            value
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if the map contained `key` as of the snapshot.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Constructs an iterator over a sub-range of entries as of the
+    /// snapshot.
+    ///
+    /// Panics if range `start > end` or if range `start == end` and both
+    /// bounds are `Excluded`.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> SnapshotRange<'_, K, V> {
+        // Sequence numbers start from 1, so `u64::MAX` is before every
+        // version of a key and 0 is after every version.
+        let start = match range.start_bound() {
+            Bound::Included(k) => Bound::Included((k.clone(), Reverse(u64::MAX))),
+            Bound::Excluded(k) => Bound::Excluded((k.clone(), Reverse(0))),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(k) => Bound::Included((k.clone(), Reverse(0))),
+            Bound::Excluded(k) => Bound::Excluded((k.clone(), Reverse(u64::MAX))),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        SnapshotRange {
+            range: self.list.versions.range((start, end)),
+            seq: self.seq,
+            last: None,
+        }
+    }
+
+    pub fn iter(&self) -> SnapshotRange<'_, K, V> {
+        self.range(..)
+    }
+}
+
+impl<'a, K, V> Drop for Snapshot<'a, K, V> {
+    fn drop(&mut self) {
+        self.list.snapshots.remove(&(self.seq, self.id));
+    }
+}
+
+/// An iterator over a sub-range of entries in a `Snapshot`.
+pub struct SnapshotRange<'a, K, V> {
+    range: VersionRange<'a, K, V>,
+    seq: u64,
+    // The key of the last version taken, older versions of it are skipped.
+    last: Option<K>,
+}
+
+impl<'a, K, V> Iterator for SnapshotRange<'a, K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let ((key, Reverse(seq)), value) = self.range.next()?;
+            if seq > self.seq || self.last.as_ref() == Some(&key) {
+                continue;
+            }
+            self.last = Some(key.clone());
+            if let Some(value) = value {
+                return Some((key, value));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn snapshot() {
+        let sk = MvccSkipList::new();
+        for i in 0..10 {
+            sk.insert(i, i);
+        }
+        let before = sk.snapshot();
+        assert_eq!(sk.insert(3, 30), Some(3));
+        assert_eq!(sk.remove(&5), Some(5));
+        assert_eq!(sk.remove(&5), None);
+        assert_eq!(sk.insert(10, 10), None);
+        let after = sk.snapshot();
+        assert_eq!(sk.insert(5, 50), None);
+
+        assert_eq!(before.seq(), 10);
+        assert_eq!(before.get(&3), Some(3));
+        assert_eq!(before.get(&5), Some(5));
+        assert!(!before.contains_key(&10));
+        assert!(before.iter().eq((0..10).map(|i| (i, i))));
+        assert_eq!(after.get(&3), Some(30));
+        assert_eq!(after.get(&5), None);
+        assert!(after.range(2..=5).eq(vec![(2, 2), (3, 30), (4, 4)]));
+        let range = after.range((Bound::Excluded(3), Bound::Excluded(10)));
+        assert!(range.map(|(k, _)| k).eq(vec![4, 6, 7, 8, 9]));
+        assert_eq!(sk.get(&5), Some(50));
+        assert_eq!(sk.snapshot().iter().count(), 11);
+    }
+
+    #[test]
+    fn gc() {
+        let sk = MvccSkipList::new();
+        for i in 0..100 {
+            sk.insert(i % 10, i);
+        }
+        assert_eq!(sk.versions.len(), 100);
+        let snapshot = sk.snapshot();
+        sk.insert(0, 100);
+        sk.remove(&1);
+        assert_eq!(sk.gc(), 90);
+        assert_eq!(sk.versions.len(), 12);
+        assert!(snapshot.iter().eq((90..100).map(|i| (i % 10, i))));
+
+        drop(snapshot);
+        // The tombstone of 1 and the version under it, and the old 0.
+        assert_eq!(sk.gc(), 3);
+        assert_eq!(sk.versions.len(), 9);
+        assert_eq!(sk.get(&0), Some(100));
+        assert_eq!(sk.get(&1), None);
+        assert!(sk.snapshot().iter().map(|(k, _)| k).eq((0..10).filter(|k| *k != 1)));
+        assert_eq!(sk.gc(), 0);
+    }
+
+    #[test]
+    fn concurrent_snapshots() {
+        let sk = Arc::new(MvccSkipList::new());
+        let writer = {
+            let sk = sk.clone();
+            thread::spawn(move || {
+                let mut rng = StdRng::seed_from_u64(17);
+                for i in 0..20000u64 {
+                    let k = rng.gen_range(0, 200);
+                    if i % 5 == 0 {
+                        sk.remove(&k);
+                    } else {
+                        sk.insert(k, i);
+                    }
+                    if i % 1000 == 0 {
+                        sk.gc();
+                    }
+                }
+            })
+        };
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let sk = sk.clone();
+                thread::spawn(move || {
+                    for _ in 0..50 {
+                        let snapshot = sk.snapshot();
+                        let first: Vec<_> = snapshot.iter().collect();
+                        sk.gc();
+                        let second: Vec<_> = snapshot.iter().collect();
+                        assert_eq!(first, second);
+                        for (k, v) in &first {
+                            assert_eq!(snapshot.get(k), Some(*v));
+                        }
+                    }
+                })
+            })
+            .collect();
+        writer.join().unwrap();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        sk.gc();
+        assert_eq!(sk.versions.len(), sk.snapshot().iter().count());
+    }
+}
@@ -0,0 +1,143 @@
+use crate::comparator::Comparator;
+use crate::set::SkipSet;
+use crate::skip::SkipList;
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+// Entries are collected first and built by `FromIterator`, which sorts them
+// (linear for input written by `Serialize`, which is sorted already) and
+// builds the towers in one pass.
+
+impl<K: Serialize, V: Serialize, C> Serialize for SkipList<K, V, C> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+struct SkipListVisitor<K, V, C> {
+    _marker: PhantomData<SkipList<K, V, C>>,
+}
+
+impl<'de, K, V, C> Visitor<'de> for SkipListVisitor<K, V, C>
+where
+    K: Deserialize<'de>,
+    V: Deserialize<'de>,
+    C: Comparator<K> + Default,
+{
+    type Value = SkipList<K, V, C>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+        let mut entries = Vec::with_capacity(access.size_hint().unwrap_or(0));
+        while let Some(entry) = access.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(entries.into_iter().collect())
+    }
+}
+
+impl<'de, K, V, C> Deserialize<'de> for SkipList<K, V, C>
+where
+    K: Deserialize<'de>,
+    V: Deserialize<'de>,
+    C: Comparator<K> + Default,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(SkipListVisitor {
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<T: Serialize> Serialize for SkipSet<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+struct SkipSetVisitor<T> {
+    _marker: PhantomData<fn() -> SkipSet<T>>,
+}
+
+impl<'de, T> Visitor<'de> for SkipSetVisitor<T>
+where
+    T: Deserialize<'de> + Ord,
+{
+    type Value = SkipSet<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+        let mut values = Vec::with_capacity(access.size_hint().unwrap_or(0));
+        while let Some(value) = access.next_element()? {
+            values.push(value);
+        }
+        Ok(values.into_iter().collect())
+    }
+}
+
+impl<'de, T> Deserialize<'de> for SkipSet<T>
+where
+    T: Deserialize<'de> + Ord,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(SkipSetVisitor {
+            _marker: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::string::{String, ToString};
+
+    #[test]
+    fn json() {
+        let mut sk = SkipList::new();
+        for (k, v) in &[("b", 2), ("a", 1), ("c", 3)] {
+            sk.insert(k.to_string(), *v);
+        }
+        let json = serde_json::to_string(&sk).unwrap();
+        assert_eq!(json, r#"{"a":1,"b":2,"c":3}"#);
+
+        let back: SkipList<String, i32> = serde_json::from_str(&json).unwrap();
+        assert!(back.iter().eq(sk.iter()));
+        let unsorted: SkipList<String, i32> =
+            serde_json::from_str(r#"{"c":3,"a":1,"b":2,"a":4}"#).unwrap();
+        let bt: BTreeMap<String, i32> =
+            serde_json::from_str(r#"{"c":3,"a":1,"b":2,"a":4}"#).unwrap();
+        assert!(unsorted.iter().eq(bt.iter()));
+
+        let set: SkipSet<i32> = serde_json::from_str("[3, 1, 2, 1]").unwrap();
+        assert_eq!(serde_json::to_string(&set).unwrap(), "[1,2,3]");
+        assert!(serde_json::from_str::<SkipSet<i32>>("{}").is_err());
+    }
+
+    #[test]
+    fn bincode() {
+        let sk: SkipList<u32, String> = (0..1000).map(|i| (i, i.to_string())).collect();
+        let bt: BTreeMap<u32, String> = sk.iter().map(|(k, v)| (*k, v.clone())).collect();
+        let bytes = bincode::serialize(&sk).unwrap();
+        assert_eq!(bytes, bincode::serialize(&bt).unwrap());
+        let back: SkipList<u32, String> = bincode::deserialize(&bytes).unwrap();
+        assert!(back.iter().eq(sk.iter()));
+        assert_eq!(back.rank(&500), Some(500));
+
+        let set: SkipSet<u32> = (0..100).rev().collect();
+        let bytes = bincode::serialize(&set).unwrap();
+        let bs: BTreeSet<u32> = bincode::deserialize(&bytes).unwrap();
+        assert!(bs.iter().eq(set.iter()));
+        let back: SkipSet<u32> = bincode::deserialize(&bytes).unwrap();
+        assert!(back.iter().eq(set.iter()));
+    }
+}
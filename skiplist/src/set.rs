@@ -0,0 +1,530 @@
+use crate::level::LevelGenerator;
+use crate::skip::{Entry, IntoIter, Iter, Range, SkipList};
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::iter::FromIterator;
+use core::iter::{FusedIterator, Peekable};
+use core::ops::RangeBounds;
+
+/// An ordered set based on a skip list, a `SkipList` whose values are `()`.
+///
+/// The API follows `std::collections::BTreeSet`.
+pub struct SkipSet<T> {
+    map: SkipList<T, ()>,
+}
+
+impl<T> SkipSet<T> {
+    pub fn new() -> SkipSet<T> {
+        SkipSet { map: SkipList::new() }
+    }
+
+    /// Creates an empty set whose tower heights are chosen by
+    /// `level_generator`.
+    pub fn with_level_generator<G>(level_generator: G) -> SkipSet<T>
+    where
+        G: LevelGenerator + 'static,
+    {
+        SkipSet {
+            map: SkipList::with_level_generator(level_generator),
+        }
+    }
+
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if the set contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Clears the set, removing all elements.
+    pub fn clear(&mut self) {
+        self.map.clear()
+    }
+
+    /// Returns the first element in the set.
+    pub fn first(&self) -> Option<&T> {
+        self.map.first_key_value().map(|(k, _)| k)
+    }
+
+    /// Returns the last element in the set.
+    pub fn last(&self) -> Option<&T> {
+        self.map.last_key_value().map(|(k, _)| k)
+    }
+
+    /// Removes and returns the first element in the set.
+    pub fn pop_first(&mut self) -> Option<T> {
+        self.map.pop_first().map(|(k, _)| k)
+    }
+
+    pub fn iter(&self) -> SetIter<'_, T> {
+        SetIter {
+            iter: self.map.iter(),
+        }
+    }
+}
+
+impl<T: Ord> SkipSet<T> {
+    /// Adds a value to the set, returning `false` if the set already had
+    /// an equal value, which is left untouched.
+    pub fn insert(&mut self, value: T) -> bool {
+        match self.map.entry(value) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(());
+                true
+            }
+        }
+    }
+
+    /// Removes a value from the set, returning whether it was present.
+    pub fn remove<Q>(&mut self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.remove(value).is_some()
+    }
+
+    /// Removes and returns the value in the set equal to `value`, if any.
+    pub fn take<Q>(&mut self, value: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.remove_entry(value).map(|(k, _)| k)
+    }
+
+    /// Returns `true` if the set contains `value`.
+    pub fn contains<Q>(&self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.contains_key(value)
+    }
+
+    /// Removes and returns the last element in the set.
+    pub fn pop_last(&mut self) -> Option<T> {
+        self.map.pop_last().map(|(k, _)| k)
+    }
+
+    /// Constructs a double-ended iterator over a sub-range of elements in
+    /// the set.
+    ///
+    /// Panics if range `start > end` or if range `start == end` and both
+    /// bounds are `Excluded`.
+    pub fn range<Q, R>(&self, range: R) -> SetRange<'_, T>
+    where
+        Q: Ord + ?Sized,
+        T: Borrow<Q>,
+        R: RangeBounds<Q>,
+    {
+        SetRange {
+            range: self.map.range(range),
+        }
+    }
+
+    /// Visits the values in `self` or `other`, in ascending order.
+    pub fn union<'a>(&'a self, other: &'a SkipSet<T>) -> Union<'a, T> {
+        Union {
+            a: self.iter().peekable(),
+            b: other.iter().peekable(),
+        }
+    }
+
+    /// Visits the values in both `self` and `other`, in ascending order.
+    pub fn intersection<'a>(&'a self, other: &'a SkipSet<T>) -> Intersection<'a, T> {
+        Intersection {
+            a: self.iter().peekable(),
+            b: other.iter().peekable(),
+        }
+    }
+
+    /// Visits the values in `self` but not in `other`, in ascending order.
+    pub fn difference<'a>(&'a self, other: &'a SkipSet<T>) -> Difference<'a, T> {
+        Difference {
+            a: self.iter().peekable(),
+            b: other.iter().peekable(),
+        }
+    }
+
+    /// Visits the values in `self` or `other` but not in both, in ascending
+    /// order.
+    pub fn symmetric_difference<'a>(&'a self, other: &'a SkipSet<T>) -> SymmetricDifference<'a, T> {
+        SymmetricDifference {
+            a: self.iter().peekable(),
+            b: other.iter().peekable(),
+        }
+    }
+
+    /// Returns `true` if every value of `self` is in `other`.
+    pub fn is_subset(&self, other: &SkipSet<T>) -> bool {
+        self.len() <= other.len() && self.difference(other).next().is_none()
+    }
+
+    /// Returns `true` if every value of `other` is in `self`.
+    pub fn is_superset(&self, other: &SkipSet<T>) -> bool {
+        other.is_subset(self)
+    }
+
+    /// Returns `true` if `self` and `other` have no value in common.
+    pub fn is_disjoint(&self, other: &SkipSet<T>) -> bool {
+        self.intersection(other).next().is_none()
+    }
+}
+
+impl<T> Default for SkipSet<T> {
+    fn default() -> SkipSet<T> {
+        SkipSet::new()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for SkipSet<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> SkipSet<T> {
+        SkipSet {
+            map: iter.into_iter().map(|value| (value, ())).collect(),
+        }
+    }
+}
+
+impl<T: Ord> Extend<T> for SkipSet<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.insert(value);
+        }
+    }
+}
+
+/// An iterator over the elements of a `SkipSet`.
+pub struct SetIter<'a, T> {
+    iter: Iter<'a, T, ()>,
+}
+
+impl<'a, T> Iterator for SetIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(k, _)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.iter.nth(n).map(|(k, _)| k)
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for SetIter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(k, _)| k)
+    }
+}
+
+impl<'a, T> ExactSizeIterator for SetIter<'a, T> {}
+
+impl<'a, T> FusedIterator for SetIter<'a, T> {}
+
+/// An iterator over a sub-range of elements in a `SkipSet`.
+pub struct SetRange<'a, T> {
+    range: Range<'a, T, ()>,
+}
+
+impl<'a, T> Iterator for SetRange<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.range.next().map(|(k, _)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.range.nth(n).map(|(k, _)| k)
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for SetRange<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.range.next_back().map(|(k, _)| k)
+    }
+}
+
+impl<'a, T> ExactSizeIterator for SetRange<'a, T> {}
+
+impl<'a, T> FusedIterator for SetRange<'a, T> {}
+
+/// An owning iterator over the elements of a `SkipSet`.
+pub struct SetIntoIter<T> {
+    iter: IntoIter<T, ()>,
+}
+
+impl<T> Iterator for SetIntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(k, _)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<T> DoubleEndedIterator for SetIntoIter<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(k, _)| k)
+    }
+}
+
+impl<T> ExactSizeIterator for SetIntoIter<T> {}
+
+impl<T> FusedIterator for SetIntoIter<T> {}
+
+impl<T> IntoIterator for SkipSet<T> {
+    type Item = T;
+    type IntoIter = SetIntoIter<T>;
+
+    fn into_iter(self) -> SetIntoIter<T> {
+        SetIntoIter {
+            iter: self.map.into_iter(),
+        }
+    }
+}
+
+/// A lazy iterator producing elements in the union of `SkipSet`s.
+pub struct Union<'a, T> {
+    a: Peekable<SetIter<'a, T>>,
+    b: Peekable<SetIter<'a, T>>,
+}
+
+impl<'a, T: Ord> Iterator for Union<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let order = match (self.a.peek(), self.b.peek()) {
+            (Some(x), Some(y)) => x.cmp(y),
+            (Some(_), None) => Ordering::Less,
+            (None, _) => Ordering::Greater,
+        };
+        match order {
+            Ordering::Less => self.a.next(),
+            Ordering::Greater => self.b.next(),
+            Ordering::Equal => {
+                self.b.next();
+                self.a.next()
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (a, b) = (self.a.len(), self.b.len());
+        (a.max(b), Some(a + b))
+    }
+}
+
+impl<'a, T: Ord> FusedIterator for Union<'a, T> {}
+
+/// A lazy iterator producing elements in the intersection of `SkipSet`s.
+pub struct Intersection<'a, T> {
+    a: Peekable<SetIter<'a, T>>,
+    b: Peekable<SetIter<'a, T>>,
+}
+
+impl<'a, T: Ord> Iterator for Intersection<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.a.peek()?.cmp(self.b.peek()?) {
+                Ordering::Less => {
+                    self.a.next();
+                }
+                Ordering::Greater => {
+                    self.b.next();
+                }
+                Ordering::Equal => {
+                    self.b.next();
+                    return self.a.next();
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.a.len().min(self.b.len())))
+    }
+}
+
+impl<'a, T: Ord> FusedIterator for Intersection<'a, T> {}
+
+/// A lazy iterator producing elements in the difference of `SkipSet`s.
+pub struct Difference<'a, T> {
+    a: Peekable<SetIter<'a, T>>,
+    b: Peekable<SetIter<'a, T>>,
+}
+
+impl<'a, T: Ord> Iterator for Difference<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let x = self.a.peek()?;
+            let order = match self.b.peek() {
+                Some(y) => x.cmp(y),
+                None => Ordering::Less,
+            };
+            match order {
+                Ordering::Less => return self.a.next(),
+                Ordering::Greater => {
+                    self.b.next();
+                }
+                Ordering::Equal => {
+                    self.a.next();
+                    self.b.next();
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.a.len()))
+    }
+}
+
+impl<'a, T: Ord> FusedIterator for Difference<'a, T> {}
+
+/// A lazy iterator producing elements in the symmetric difference of
+/// `SkipSet`s.
+pub struct SymmetricDifference<'a, T> {
+    a: Peekable<SetIter<'a, T>>,
+    b: Peekable<SetIter<'a, T>>,
+}
+
+impl<'a, T: Ord> Iterator for SymmetricDifference<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let order = match (self.a.peek(), self.b.peek()) {
+                (Some(x), Some(y)) => x.cmp(y),
+                (Some(_), None) => Ordering::Less,
+                (None, _) => Ordering::Greater,
+            };
+            match order {
+                Ordering::Less => return self.a.next(),
+                Ordering::Greater => return self.b.next(),
+                Ordering::Equal => {
+                    self.a.next();
+                    self.b.next();
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.a.len() + self.b.len()))
+    }
+}
+
+impl<'a, T: Ord> FusedIterator for SymmetricDifference<'a, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeSet;
+    use std::vec;
+    use std::vec::Vec;
+
+    #[test]
+    fn set_basic() {
+        let mut set = SkipSet::new();
+        assert!(set.insert(3));
+        assert!(set.insert(1));
+        assert!(!set.insert(3));
+        assert!(set.insert(2));
+        assert_eq!(set.len(), 3);
+        assert!(set.contains(&2));
+        assert!(!set.contains(&4));
+        assert!(set.iter().eq([1, 2, 3].iter()));
+        assert!(set.range(2..).eq([2, 3].iter()));
+        assert!(set.range(..=2).rev().eq([2, 1].iter()));
+        assert_eq!(set.first(), Some(&1));
+        assert_eq!(set.last(), Some(&3));
+
+        assert!(set.remove(&2));
+        assert!(!set.remove(&2));
+        assert_eq!(set.take(&3), Some(3));
+        assert_eq!(set.pop_first(), Some(1));
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn set_algebra() {
+        let a: Vec<i32> = vec![1, 3, 5, 7, 9];
+        let b: Vec<i32> = vec![3, 4, 5, 10];
+        let mut sa = SkipSet::new();
+        let mut sb = SkipSet::new();
+        a.iter().for_each(|x| {
+            sa.insert(*x);
+        });
+        b.iter().for_each(|x| {
+            sb.insert(*x);
+        });
+
+        assert!(sa.union(&sb).eq([1, 3, 4, 5, 7, 9, 10].iter()));
+        assert!(sa.intersection(&sb).eq([3, 5].iter()));
+        assert!(sa.difference(&sb).eq([1, 7, 9].iter()));
+        assert!(sb.difference(&sa).eq([4, 10].iter()));
+        assert!(sa.symmetric_difference(&sb).eq([1, 4, 7, 9, 10].iter()));
+        assert!(!sa.is_subset(&sb));
+        assert!(!sa.is_disjoint(&sb));
+
+        sb.remove(&4);
+        sb.remove(&10);
+        assert!(sb.is_subset(&sa));
+        assert!(sa.is_superset(&sb));
+        sa.remove(&3);
+        sa.remove(&5);
+        assert!(sa.is_disjoint(&sb));
+
+        let empty = SkipSet::new();
+        assert!(empty.is_subset(&sa));
+        assert!(sa.union(&empty).eq(sa.iter()));
+        assert_eq!(sa.intersection(&empty).next(), None);
+    }
+
+    #[test]
+    fn same_as_btreeset() {
+        let mut rng = StdRng::seed_from_u64(8);
+        for _ in 0..50 {
+            let mut sa = SkipSet::new();
+            let mut sb = SkipSet::new();
+            let mut ba = BTreeSet::new();
+            let mut bb = BTreeSet::new();
+            for _ in 0..rng.gen_range(0, 100) {
+                let x = rng.gen_range(0, 100);
+                assert_eq!(sa.insert(x), ba.insert(x));
+            }
+            for _ in 0..rng.gen_range(0, 100) {
+                let x = rng.gen_range(0, 100);
+                assert_eq!(sb.insert(x), bb.insert(x));
+            }
+
+            assert!(sa.union(&sb).eq(ba.union(&bb)));
+            assert!(sa.intersection(&sb).eq(ba.intersection(&bb)));
+            assert!(sa.difference(&sb).eq(ba.difference(&bb)));
+            assert!(sa.symmetric_difference(&sb).eq(ba.symmetric_difference(&bb)));
+            assert_eq!(sa.is_subset(&sb), ba.is_subset(&bb));
+            assert_eq!(sa.is_disjoint(&sb), ba.is_disjoint(&bb));
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use crate::skip::{Range, SkipList};
+use core::ops::RangeBounds;
+use std::borrow::Borrow;
+use std::cmp::Reverse;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BinaryHeap;
+use std::hash::{Hash, Hasher};
+use std::sync::{RwLock, RwLockReadGuard};
+
+/// An ordered map shared between threads, spreading its keys by hash over
+/// `SkipList` shards behind `RwLock`s.
+///
+/// Writes lock a single shard, so writers to different shards don't wait
+/// for each other. Ordered reads across the whole map go through `read`,
+/// which locks every shard for reading and merges them.
+///
+/// ```
+/// use skiplist::ShardedSkipList;
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// let sk = Arc::new(ShardedSkipList::new(4));
+/// let writers: Vec<_> = (0..4)
+///     .map(|t| {
+///         let sk = sk.clone();
+///         thread::spawn(move || {
+///             for i in 0..100 {
+///                 sk.insert(i * 4 + t, t);
+///             }
+///         })
+///     })
+///     .collect();
+/// for writer in writers {
+///     writer.join().unwrap();
+/// }
+/// assert!(sk.read().iter().map(|(k, _)| *k).eq(0..400));
+/// ```
+pub struct ShardedSkipList<K, V> {
+    shards: Vec<RwLock<SkipList<K, V>>>,
+}
+
+impl<K, V> ShardedSkipList<K, V> {
+    /// Creates a map with `shards` shards.
+    ///
+    /// Panics if `shards` is 0.
+    pub fn new(shards: usize) -> ShardedSkipList<K, V> {
+        assert!(shards > 0, "shards must be greater than 0");
+        ShardedSkipList {
+            shards: (0..shards).map(|_| RwLock::new(SkipList::new())).collect(),
+        }
+    }
+
+    /// Returns the number of shards.
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Returns the number of entries. Writes to other shards may happen
+    /// while it counts.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.read().unwrap().len()).sum()
+    }
+
+    /// Returns `true` if the map has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Locks every shard for reading, giving a consistent view of the whole
+    /// map until the guard is dropped.
+    pub fn read(&self) -> ShardedReadGuard<'_, K, V> {
+        // Shards are always locked in the same order, and writers hold a
+        // single shard, so this never deadlocks.
+        ShardedReadGuard {
+            shards: self.shards.iter().map(|shard| shard.read().unwrap()).collect(),
+        }
+    }
+}
+
+impl<K: Hash + Ord, V> ShardedSkipList<K, V> {
+    /// Inserts a key-value pair, returning the old value of the key.
+    pub fn insert(&self, k: K, v: V) -> Option<V> {
+        self._shard(&k).write().unwrap().insert(k, v)
+    }
+
+    /// Removes a key, returning its value.
+    pub fn remove<Q>(&self, q: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Ord + ?Sized,
+    {
+        self._shard(q).write().unwrap().remove(q)
+    }
+
+    /// Returns a copy of the value of a key.
+    pub fn get<Q>(&self, q: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Ord + ?Sized,
+        V: Clone,
+    {
+        self._shard(q).read().unwrap().get(q).cloned()
+    }
+
+    /// Returns `true` if the map contains a value for the specified key.
+    pub fn contains_key<Q>(&self, q: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Ord + ?Sized,
+    {
+        self._shard(q).read().unwrap().contains_key(q)
+    }
+
+    // The shard of a key. `Borrow` requires the borrowed form to hash like
+    // the key.
+    fn _shard<Q: Hash + ?Sized>(&self, q: &Q) -> &RwLock<SkipList<K, V>> {
+        let mut hasher = DefaultHasher::new();
+        q.hash(&mut hasher);
+        &self.shards[(hasher.finish() % self.shards.len() as u64) as usize]
+    }
+}
+
+impl<K, V> Default for ShardedSkipList<K, V> {
+    /// A map with 16 shards.
+    fn default() -> ShardedSkipList<K, V> {
+        ShardedSkipList::new(16)
+    }
+}
+
+/// Read locks on every shard of a `ShardedSkipList`, created by `read`.
+pub struct ShardedReadGuard<'a, K, V> {
+    shards: Vec<RwLockReadGuard<'a, SkipList<K, V>>>,
+}
+
+impl<'a, K, V> ShardedReadGuard<'a, K, V> {
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.len()).sum()
+    }
+
+    /// Returns `true` if the map has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<'a, K: Ord, V> ShardedReadGuard<'a, K, V> {
+    /// Returns the value of a key.
+    pub fn get<Q>(&self, q: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.shards.iter().find_map(|shard| shard.get(q))
+    }
+
+    /// Visits the entries of all shards in key order.
+    pub fn iter(&self) -> ShardedIter<'_, K, V> {
+        self.range::<K, _>(..)
+    }
+
+    /// Visits the entries inside `range` of all shards in key order.
+    ///
+    /// Panics if range `start > end` or if range `start == end` and both
+    /// bounds are `Excluded`.
+    pub fn range<Q, R>(&self, range: R) -> ShardedIter<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let bounds = (range.start_bound(), range.end_bound());
+        let ranges: Vec<_> = self.shards.iter().map(|shard| shard.range(bounds)).collect();
+        ShardedIter::new(ranges)
+    }
+}
+
+/// An iterator merging the shards of a `ShardedSkipList` in key order.
+pub struct ShardedIter<'a, K, V> {
+    ranges: Vec<Range<'a, K, V>>,
+    // The next key of every shard, the smallest on top. A key lives in a
+    // single shard, so there are no ties.
+    heads: BinaryHeap<Reverse<(&'a K, usize)>>,
+    values: Vec<Option<&'a V>>,
+    len: usize,
+}
+
+impl<'a, K: Ord, V> ShardedIter<'a, K, V> {
+    fn new(ranges: Vec<Range<'a, K, V>>) -> ShardedIter<'a, K, V> {
+        let mut iter = ShardedIter {
+            len: ranges.iter().map(|range| range.len()).sum(),
+            heads: BinaryHeap::with_capacity(ranges.len()),
+            values: ranges.iter().map(|_| None).collect(),
+            ranges,
+        };
+        for i in 0..iter.ranges.len() {
+            iter._pull(i);
+        }
+        iter
+    }
+
+    // Moves the next entry of shard `i` into the heads.
+    fn _pull(&mut self, i: usize) {
+        if let Some((k, v)) = self.ranges[i].next() {
+            self.heads.push(Reverse((k, i)));
+            self.values[i] = Some(v);
+        }
+    }
+}
+
+impl<'a, K: Ord, V> Iterator for ShardedIter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((k, i)) = self.heads.pop()?;
+        let v = self.values[i].take().unwrap();
+        self._pull(i);
+        self.len -= 1;
+        Some((k, v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, K: Ord, V> ExactSizeIterator for ShardedIter<'a, K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeMap;
+    use std::ops::Bound;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn concurrent_writers() {
+        let sk = Arc::new(ShardedSkipList::new(8));
+        let writers: Vec<_> = (0..4u64)
+            .map(|t| {
+                let sk = sk.clone();
+                thread::spawn(move || {
+                    let mut rng = StdRng::seed_from_u64(t);
+                    let mut map = BTreeMap::new();
+                    // Every thread owns the keys equal to `t` modulo 4.
+                    for _ in 0..5000 {
+                        let k = rng.gen_range(0, 2000) * 4 + t;
+                        if rng.gen_bool(0.2) {
+                            assert_eq!(sk.remove(&k), map.remove(&k));
+                        } else {
+                            assert_eq!(sk.insert(k, t), map.insert(k, t));
+                        }
+                    }
+                    map
+                })
+            })
+            .collect();
+        let mut expected = BTreeMap::new();
+        for writer in writers {
+            expected.append(&mut writer.join().unwrap());
+        }
+
+        assert_eq!(sk.len(), expected.len());
+        let read = sk.read();
+        assert_eq!(read.len(), expected.len());
+        assert_eq!(read.iter().len(), expected.len());
+        assert!(read.iter().eq(expected.iter()));
+        let range = (Bound::Excluded(1000), Bound::Included(3000));
+        assert!(read.range(range).eq(expected.range(range)));
+        for k in 0..100 {
+            assert_eq!(read.get(&k), expected.get(&k));
+        }
+        drop(read);
+        for k in 0..100 {
+            assert_eq!(sk.get(&k), expected.get(&k).copied());
+            assert_eq!(sk.contains_key(&k), expected.contains_key(&k));
+        }
+    }
+
+    #[test]
+    fn single_shard() {
+        let sk = ShardedSkipList::new(1);
+        assert!(sk.is_empty());
+        sk.insert("b".to_string(), 2);
+        sk.insert("a".to_string(), 1);
+        assert_eq!(sk.get("a"), Some(1));
+        assert_eq!(sk.remove("b"), Some(2));
+        assert!(sk.read().iter().eq(vec![(&"a".to_string(), &1)]));
+    }
+}
@@ -1,23 +1,55 @@
-use rand::random;
-use std::borrow::Borrow;
-use std::cmp::Ordering;
-use core::ops::{Bound, Index, RangeBounds};
+use crate::comparator::{Comparator, OrdComparator};
+use crate::error::SkipListError;
+use crate::level::GeometricalLevelGenerator;
+use crate::level::LevelGenerator;
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::fmt::{self, Debug};
+use core::hash::{Hash, Hasher};
+use core::iter::FromIterator;
+use core::iter::FusedIterator;
 use core::marker::PhantomData;
-use std::fmt::Debug;
+use core::ops::{Bound, Index, RangeBounds};
+
+mod cursor;
+mod entry;
+
+pub use cursor::CursorMut;
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
 
+// Positions used by `widths`: the head is at 0, nodes are at `1..=len`, and
+// a null next stands for position `len + 1`.
 #[derive(Debug)]
 struct Node<K, V> {
     nexts: Vec<*mut Node<K, V>>,
+    // Distance to the node pointed by `nexts` on the same level.
+    widths: Vec<usize>,
     next: Option<Box<Node<K, V>>>,
+    // Back link of the `next` chain, null for the first node.
+    prev: *mut Node<K, V>,
     key: K,
     value: V,
 }
 
 impl<K, V> Node<K, V> {
+    // Drop a `next` chain node by node. Letting the `Box` go out of scope
+    // would drop the rest of the chain recursively and overflow the stack
+    // for long chains.
+    fn _drop_chain(mut next: Option<Box<Node<K, V>>>) {
+        while let Some(mut node) = next {
+            next = node.next.take();
+        }
+    }
+
     fn new(levels: usize, k: K, v: V) -> Node<K, V> {
         Node {
-            nexts: vec![std::ptr::null_mut(); levels],
+            nexts: vec![core::ptr::null_mut(); levels],
+            widths: vec![0; levels],
             next: None,
+            prev: core::ptr::null_mut(),
             key: k,
             value: v,
         }
@@ -27,380 +59,405 @@ impl<K, V> Node<K, V> {
     // Returns contents that was removed.
     // Caller must sort out the `nexts` before removing next node.
     fn _remove_next(&mut self) -> Option<(K, V)> {
+        let self_ptr: *mut _ = self;
         match self.next.take() {
             Some(mut node) => {
                 self.next = node.next.take();
+                if let Some(next) = self.next.as_mut() {
+                    next.prev = self_ptr;
+                }
                 Some((node.key, node.value))
             }
             None => None,
         }
     }
 
-    // Replace next node with new node.
-    // Returns contents that was replaced.
-    // Caller must sort out the `nexts` before replacing next node.
-    // It will be pannic if there is no next node.
-    fn _replace_next(&mut self, mut node: Box<Self>) -> Option<(K, V)> {
-        match self.next.take() {
-            Some(old) => {
-                node.next = old.next;
-                self.next = Some(node);
-                Some((old.key, old.value))
-            }
-            None => unreachable!(),
-        }
-    }
-
     // Insert a node right after.
     // Caller must sort out the `nexts` before inserting.
     fn _insert_next(&mut self, mut node: Box<Self>) {
-        match self.next.take() {
-            Some(old) => {
-                node.next = Some(old);
-                self.next = Some(node);
-            }
-            None => {
-                node.next = None;
-                self.next = Some(node);
+        let node_ptr: *mut _ = &mut *node;
+        node.prev = self;
+        node.next = self.next.take();
+        if let Some(next) = node.next.as_mut() {
+            next.prev = node_ptr;
+        }
+        self.next = Some(node);
+    }
+
+    // Walk `n` nodes forward from `node`, taking the highest level that
+    // doesn't pass the target. There must be at least `n` nodes after `node`.
+    fn _forward(mut node: *mut Node<K, V>, mut n: usize) -> *mut Node<K, V> {
+        while n > 0 {
+            let current = unsafe { &*node };
+            let mut level = current.nexts.len() - 1;
+            while current.nexts[level].is_null() || current.widths[level] > n {
+                level -= 1;
             }
+            n -= current.widths[level];
+            node = current.nexts[level];
         }
+        node
     }
 }
 
-struct SkipList<K, V> {
+// Pre nodes of every level and their positions, a null pointer stands for
+// the head.
+type PreNodes<K, V> = (Vec<*mut Node<K, V>>, Vec<usize>);
+
+/// An ordered map based on a skip list.
+///
+/// The API follows `std::collections::BTreeMap`, so a `SkipList` can be
+/// used wherever an ordered map is expected. Every link also records how
+/// many entries it skips, which gives `O(log n)` positional access through
+/// `get_by_index` and `rank`.
+///
+/// Keys are ordered by `Ord` unless the list is built with another
+/// [`Comparator`], see `with_comparator`.
+pub struct SkipList<K, V, C = OrdComparator> {
     nexts: Vec<*mut Node<K, V>>,
+    widths: Vec<usize>,
     next: Option<Box<Node<K, V>>>,
+    len: usize,
+    level_generator: Box<dyn LevelGenerator>,
+    comparator: C,
+    // Tail nodes of every level and their positions, recorded by `insert`
+    // after appending so the next greater key skips the search. `_link`
+    // keeps it up to date, any other change to the structure of the list
+    // drops it.
+    finger: Option<PreNodes<K, V>>,
 }
 
-struct Range<'a, K, V> {
-    front: Option<&'a Node<K, V>>,
-    back: *const Node<K, V>,
-}
+// The list owns its nodes like a `Box` does, the raw pointers never escape
+// and every reference it hands out borrows the list, so it is as thread safe
+// as its keys, values and comparator. Level generators are `Send` and
+// `Sync`.
+unsafe impl<K: Send, V: Send, C: Send> Send for SkipList<K, V, C> {}
 
-impl<'a, K: Ord, V> Iterator for Range<'a, K, V> {
-    type Item = (&'a K, &'a V);
+unsafe impl<K: Sync, V: Sync, C: Sync> Sync for SkipList<K, V, C> {}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        // println!("next trace 0 {:?}", self.back);
+/// An iterator over a sub-range of entries in a `SkipList`.
+pub struct Range<'a, K, V> {
+    front: *mut Node<K, V>,
+    back: *mut Node<K, V>,
+    len: usize,
+    _marker: PhantomData<&'a Node<K, V>>,
+}
 
-        if self.back.is_null() {
-            return None;
-        }
+// A `Range` only hands out shared references, like `&'a (K, V)`.
+unsafe impl<'a, K: Sync, V: Sync> Send for Range<'a, K, V> {}
 
-        // println!("next trace 1");
+unsafe impl<'a, K: Sync, V: Sync> Sync for Range<'a, K, V> {}
 
-        match self.front.take() {
-            Some(node) => {
-                let node_ptr :*const _ = node;
-                if node_ptr != self.back {
-                    self.front = node.next.as_ref().map(|node| { & **node});
-                }
-                Some((&node.key, &node.value))
-            }
-            None => None,
-        }
+impl<'a, K, V> Clone for Range<'a, K, V> {
+    fn clone(&self) -> Range<'a, K, V> {
+        Range::new(self.front, self.back, self.len)
     }
 }
 
-struct RangeMut<'a, K :'a, V: 'a> {
-    front: Option<&'a mut Node<K, V>>,
-    back: *const Node<K, V>,
+impl<'a, K, V> Range<'a, K, V> {
+    fn new(front: *mut Node<K, V>, back: *mut Node<K, V>, len: usize) -> Range<'a, K, V> {
+        Range { front, back, len, _marker: PhantomData }
+    }
 }
 
-impl<'a, K: Ord, V> Iterator for RangeMut<'a, K, V> {
-    type Item = (&'a K, &'a mut V);
+impl<'a, K, V> Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.back.is_null() {
+        if self.len == 0 {
             return None;
         }
 
-        match self.front.take() {
-            Some(node) => {
-                let node_ptr :*const _ = node;
-                if node_ptr != self.back {
-                    self.front = node.next.as_mut().map(|node| { &mut **node});
-                }
-                Some((&node.key, &mut node.value))
-            }
-            None => None,
-        }
+        let node = unsafe { &*self.front };
+        self.front = node.nexts[0];
+        self.len -= 1;
+        Some((&node.key, &node.value))
     }
-}
 
-impl<K: Ord + Debug, V: Debug> SkipList<K, V> {
-    pub fn new() -> SkipList<K, V> {
-        SkipList {
-            nexts: vec![],
-            next: None,
-        }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
     }
-    // TODO
-    // range
-    // iter
-    // split
-    // contains
-    // clears
 
-    fn pop(&mut self) -> Option<(K, V)> {
-        match self.next.take() {
-            Some(mut node) => {
-                self.next = node.next.take();
-                for (i, n) in node.nexts.iter().enumerate() {
-                    self.nexts[i] = *n;
-                }
-                if *self.nexts.last().unwrap() == std::ptr::null_mut() {
-                    self.nexts.pop();
-                }
-                Some((node.key, node.value))
-            },
-            None => None,
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        if n >= self.len {
+            self.len = 0;
+            return None;
         }
+
+        self.front = Node::_forward(self.front, n);
+        self.len -= n;
+        self.next()
     }
+}
 
-    #[allow(dead_code)]
-    fn range<T: ?Sized+Debug, R>(&self, range: R) -> Range<'_, K, V> 
-        where T: Ord, K: Borrow<T>, R: RangeBounds<T> {
-        if self.next.is_none() {
-            return Range { front: None, back: std::ptr::null() };
+impl<'a, K, V> DoubleEndedIterator for Range<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
         }
 
-        let front_bound = match range.start_bound() {
-            Bound::Unbounded => Some(&**self.next.as_ref().unwrap()),
-            Bound::Included(key) => self._front_include(key),
-            Bound::Excluded(key) => self._front_exclude(key),
-        };
+        let node = unsafe { &*self.back };
+        self.back = node.prev;
+        self.len -= 1;
+        Some((&node.key, &node.value))
+    }
+}
 
-        // println!("first nexts trace {:?}", self.nexts);
-        // println!("first trace {:?}", self.next);
-        // println!("range trace 1 {:?}", front_bound);
+impl<'a, K, V> ExactSizeIterator for Range<'a, K, V> {}
 
-        if front_bound.is_none() {
-            return Range { front: None, back: std::ptr::null() };
-        }
+impl<'a, K, V> FusedIterator for Range<'a, K, V> {}
 
-        // println!("range trace 2");
+/// A mutable iterator over a sub-range of entries in a `SkipList`.
+pub struct RangeMut<'a, K: 'a, V: 'a> {
+    front: *mut Node<K, V>,
+    back: *mut Node<K, V>,
+    len: usize,
+    _marker: PhantomData<&'a mut Node<K, V>>,
+}
 
-        let front_key = &front_bound.as_ref().unwrap().key;
+// A `RangeMut` hands out `(&'a K, &'a mut V)`.
+unsafe impl<'a, K: Sync, V: Send> Send for RangeMut<'a, K, V> {}
 
-        let back_bound = match range.end_bound() {
-            Bound::Unbounded => self._get_last_node(),
-            Bound::Included(key) => {
-                // println!("back_bound included");
-                match key.cmp(front_key.borrow()) {
-                    Ordering::Greater | Ordering::Equal => self._back_include_ptr(key),
-                    Ordering::Less => std::ptr::null(),
-                }
-            },
-            Bound::Excluded(key) => {
-                // println!("back_bound excluded");
-                match key.cmp(front_key.borrow()) {
-                    Ordering::Greater => {
-                        // println!("back_bound greater");
-                        self._back_exclude_ptr(key)
-                    }
-                    Ordering::Less | Ordering::Equal => std::ptr::null(),
-                }
-            }
-        };
+unsafe impl<'a, K: Sync, V: Sync> Sync for RangeMut<'a, K, V> {}
+
+impl<'a, K, V> RangeMut<'a, K, V> {
+    fn new(front: *mut Node<K, V>, back: *mut Node<K, V>, len: usize) -> RangeMut<'a, K, V> {
+        RangeMut { front, back, len, _marker: PhantomData }
+    }
+}
 
-        // println!("range trace 3");
+impl<'a, K, V> Iterator for RangeMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
 
-        if back_bound.is_null() {
-            return Range { front: None, back: std::ptr::null() };
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
         }
 
-        // println!("range trace 4 {:?}", unsafe { &(&*back_bound).key });
+        let node = unsafe { &mut *self.front };
+        self.front = node.nexts[0];
+        self.len -= 1;
+        Some((&node.key, &mut node.value))
+    }
 
-        Range { front: front_bound, back: back_bound }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
     }
 
-    #[allow(dead_code)]
-    fn range_mut<Q: ?Sized, R>(&mut self, range: R) -> RangeMut<'_, K, V>
-    where Q: Ord + Debug, K: Borrow<Q>, R: RangeBounds<Q> {
-        if self.next.is_none() {
-            return RangeMut { front: None, back: std::ptr::null() };
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        if n >= self.len {
+            self.len = 0;
+            return None;
         }
 
-        let front_bound_ptr = match range.start_bound() {
-            Bound::Unbounded => self.nexts[0],
-            Bound::Included(key) => self._front_include_ptr(key),
-            Bound::Excluded(key) => self._front_exclude_ptr(key),
-        };
+        self.front = Node::_forward(self.front, n);
+        self.len -= n;
+        self.next()
+    }
+}
 
-        if front_bound_ptr.is_null() {
-            return RangeMut { front: None, back: std::ptr::null() };
+impl<'a, K, V> DoubleEndedIterator for RangeMut<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
         }
 
-        let front_bound = unsafe { Some(&mut *front_bound_ptr)};
-        let front_key = &front_bound.as_ref().unwrap().key;
+        let node = unsafe { &mut *self.back };
+        self.back = node.prev;
+        self.len -= 1;
+        Some((&node.key, &mut node.value))
+    }
+}
 
-        let back_bound = match range.end_bound() {
-            Bound::Unbounded => self._get_last_node(),
-            Bound::Included(key) => {
-                match key.cmp(front_key.borrow()) {
-                    Ordering::Greater | Ordering::Equal => self._back_include_ptr(key),
-                    Ordering::Less => std::ptr::null(),
-                }
-            },
-            Bound::Excluded(key) => {
-                match key.cmp(front_key.borrow()) {
-                    Ordering::Greater => self._back_exclude_ptr(key),
-                    Ordering::Less | Ordering::Equal => std::ptr::null(),
-                }
-            }
-        };
+impl<'a, K, V> ExactSizeIterator for RangeMut<'a, K, V> {}
 
-        if back_bound.is_null() {
-            return RangeMut { front: None, back: std::ptr::null() };
-        }
+impl<'a, K, V> FusedIterator for RangeMut<'a, K, V> {}
 
-        RangeMut { front: front_bound, back: back_bound }
+impl<K, V> SkipList<K, V> {
+    /// Creates an empty list with the default `GeometricalLevelGenerator`,
+    /// which is seeded from the thread RNG, or with a fixed seed without
+    /// `std`.
+    pub fn new() -> SkipList<K, V> {
+        SkipList::with_level_generator(GeometricalLevelGenerator::default())
     }
 
-    fn _front_include<Q: ?Sized>(&self, key :&Q) -> Option<& Node<K, V>>
-    where K: Borrow<Q>,
-          Q: Ord + Debug,
+    /// Creates an empty list whose tower heights are chosen by
+    /// `level_generator`.
+    ///
+    /// A seeded generator makes the shape of the list reproducible:
+    ///
+    /// ```
+    /// use skiplist::{GeometricalLevelGenerator, SkipList};
+    ///
+    /// let mut sk = SkipList::with_level_generator(GeometricalLevelGenerator::seeded(42));
+    /// sk.insert(1, "a");
+    /// assert_eq!(sk.get(&1), Some(&"a"));
+    /// ```
+    pub fn with_level_generator<G>(level_generator: G) -> SkipList<K, V>
+    where
+        G: LevelGenerator + 'static,
     {
+        SkipList::with_comparator_and_level_generator(OrdComparator, level_generator)
+    }
+}
 
-        // println!("front_include: {:?}", key);
-        if self.next.is_none() {
-            return None
-        }
-
-        let pre_ptr = self._get_pre_node(key);
-        // println!("front_include pre_ptr: {:?}", pre_ptr);
-        if !pre_ptr.is_null() {
-            let pre = unsafe {
-                &*pre_ptr
-            };
-            match pre.next.as_ref() {
-                None => None,
-                Some(node) => {
-                    Some(&**node)
-                }
-            }
-        } else {
-            Some(&**self.next.as_ref().unwrap())
-        }
+impl<K, V, C> SkipList<K, V, C> {
+    /// Creates an empty list whose keys are ordered by `comparator`.
+    pub fn with_comparator(comparator: C) -> SkipList<K, V, C> {
+        SkipList::with_comparator_and_level_generator(
+            comparator,
+            GeometricalLevelGenerator::default(),
+        )
     }
 
-    fn _front_include_ptr<Q: ?Sized>(&self, key :&Q) -> *mut Node<K, V>
-    where K: Borrow<Q>,
-          Q: Ord + Debug,
+    /// Creates an empty list whose keys are ordered by `comparator` and
+    /// whose tower heights are chosen by `level_generator`.
+    pub fn with_comparator_and_level_generator<G>(
+        comparator: C,
+        level_generator: G,
+    ) -> SkipList<K, V, C>
+    where
+        G: LevelGenerator + 'static,
     {
-        if self.next.is_none() {
-            return std::ptr::null_mut();
-        }
+        SkipList::_with_parts(comparator, Box::new(level_generator))
+    }
 
-        let pre_ptr = self._get_pre_node(key);
-        if !pre_ptr.is_null() {
-            let pre = unsafe { &*pre_ptr };
-            match pre.next.as_ref() {
-                None => std::ptr::null_mut(),
-                Some(_) => pre.nexts[0],
-            }
-        } else {
-            self.nexts[0]
+    fn _with_parts(comparator: C, level_generator: Box<dyn LevelGenerator>) -> SkipList<K, V, C> {
+        SkipList {
+            nexts: vec![],
+            widths: vec![],
+            next: None,
+            len: 0,
+            level_generator,
+            comparator,
+            finger: None,
         }
     }
 
-    fn _front_exclude<Q: ?Sized>(&self, key :&Q) -> Option<& Node<K, V>>
-    where K: Borrow<Q>,
-          Q: Ord + Debug,
-    {
-        if self.next.is_none() {
-            return None
-        }
+    /// Returns a reference to the comparator ordering the keys.
+    pub fn comparator(&self) -> &C {
+        &self.comparator
+    }
 
-        let pre_ptr = self._get_pre_node(key);
-        let current;
-        if !pre_ptr.is_null() {
-            let pre = unsafe { &*pre_ptr };
-            match pre.next.as_ref() {
-                None => current = None,
-                Some(node) => current = Some(&**node),
-            };
-        } else {
-            current = Some(&**self.next.as_ref().unwrap());
-        }
+    /// Returns the number of elements in the list.
+    pub fn len(&self) -> usize {
+        self.len
+    }
 
-        current.and_then(|node| {
-            match key.cmp(node.key.borrow()) {
-                Ordering::Equal => node.next.as_ref().map(|next| {&**next}),
-                Ordering::Greater => Some(node),
-                Ordering::Less => unreachable!(),
-            }
-        })
+    /// Returns `true` if the list contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
     }
 
-    fn _front_exclude_ptr<Q: ?Sized>(&self, key :&Q) -> *mut Node<K, V>
-    where K: Borrow<Q>,
-          Q: Ord + Debug,
-    {
-        if self.next.is_none() {
-            return std::ptr::null_mut()
+    /// Clears the list, removing all elements.
+    pub fn clear(&mut self) {
+        self.finger = None;
+        self.nexts.clear();
+        self.widths.clear();
+        self._drop_chain();
+        self.len = 0;
+    }
+
+    /// Returns the first key-value pair in the list.
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        self.next.as_deref().map(|node| (&node.key, &node.value))
+    }
+
+    /// Returns the last key-value pair in the list.
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        let last = self._get_last_node();
+        if last.is_null() {
+            return None;
         }
+        let node = unsafe { &*last };
+        Some((&node.key, &node.value))
+    }
 
-        let pre_ptr = self._get_pre_node(key);
-        let current;
-        let mut current_ptr = std::ptr::null_mut();
-        if !pre_ptr.is_null() {
-            let pre = unsafe { &*pre_ptr };
-            match pre.next.as_ref() {
-                None => current = None,
-                Some(node) => {
-                    current = Some(&**node);
-                    current_ptr = pre.nexts[0];
-                }
-            };
-        } else {
-            current = Some(&**self.next.as_ref().unwrap());
-            current_ptr = self.nexts[0];
+    /// Removes and returns the first element in the list.
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        let first = self._get_first_node();
+        if first.is_null() {
+            return None;
+        }
+        let pres = vec![core::ptr::null_mut(); self.nexts.len()];
+        Some(self._unlink(&pres, first))
+    }
+
+    /// Returns the key-value pair at position `index` in the sorted order,
+    /// starting from 0.
+    pub fn get_by_index(&self, index: usize) -> Option<(&K, &V)> {
+        let node = self._get_node_at(index + 1);
+        if node.is_null() {
+            return None;
         }
+        let node = unsafe { &*node };
+        Some((&node.key, &node.value))
+    }
 
-        if current.is_none() {
-            return std::ptr::null_mut();
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            range: Range::new(self._get_first_node(), self._get_last_node(), self.len),
         }
+    }
 
-        let node = current.unwrap();
-        match key.cmp(node.key.borrow()) {
-            Ordering::Equal => node.nexts[0],
-            Ordering::Greater => current_ptr,
-            Ordering::Less => unreachable!(),
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            range: RangeMut::new(self._get_first_node(), self._get_last_node(), self.len),
         }
     }
 
-    fn _back_include_ptr<Q: ?Sized>(&self, key :&Q) -> *const Node<K,V>
-    where K: Borrow<Q>,
-          Q: Ord + Debug,
+    /// Retains only the entries specified by the predicate, in one pass.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
     {
-        let pre_ptr = self._get_pre_node(key);
-        let current;
-        if !pre_ptr.is_null() {
-            let pre_node = unsafe { &*pre_ptr };
-            current = pre_node.next.as_ref().map(|node| {&**node})
-        } else {
-            current = Some(&**self.next.as_ref().unwrap())
+        self.extract_if(|k, v| !f(k, v)).for_each(drop);
+    }
+
+    /// Creates an iterator which walks the list in order and removes the
+    /// entries for which the predicate returns `true`.
+    ///
+    /// Entries are removed lazily, an entry the iterator doesn't reach is
+    /// kept.
+    pub fn extract_if<F>(&mut self, pred: F) -> ExtractIf<'_, K, V, F, C>
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        ExtractIf {
+            cursor: self._cursor_front(),
+            pred,
         }
+    }
 
-        match current {
-            None => pre_ptr as *const _,
-            Some(node) => {
-                match key.cmp(node.key.borrow()) {
-                    Ordering::Equal => node as *const _,
-                    Ordering::Greater => pre_ptr as *const _,
-                    Ordering::Less => unreachable!(),
-                }
-            }
+    // Returns the tower owned by `pre`, a null `pre` stands for the head.
+    fn _tower_of(&mut self, pre: *mut Node<K, V>) -> (&mut Vec<*mut Node<K, V>>, &mut Vec<usize>) {
+        if pre.is_null() {
+            (&mut self.nexts, &mut self.widths)
+        } else {
+            unsafe { (&mut (*pre).nexts, &mut (*pre).widths) }
         }
     }
 
-    fn _back_exclude_ptr<Q: ?Sized>(&self, key :&Q) -> *const Node<K, V>
-    where K: Borrow<Q>,
-          Q: Ord + Debug,
-    {
-        self._get_pre_node(key) as *const _
+    fn _drop_chain(&mut self) {
+        Node::_drop_chain(self.next.take());
+    }
+
+    // Move every entry out into an owning iterator, leaving the list empty.
+    fn _take_all(&mut self) -> IntoIter<K, V> {
+        self.finger = None;
+        let back = self._get_last_node();
+        self.nexts.clear();
+        self.widths.clear();
+        let len = core::mem::replace(&mut self.len, 0);
+        IntoIter { next: self.next.take(), back, len }
+    }
+
+    // Drop the empty levels on top of the head.
+    fn _shrink(&mut self) {
+        while let Some(true) = self.nexts.last().map(|p| p.is_null()) {
+            self.nexts.pop();
+            self.widths.pop();
+        }
     }
 
     // Remove next node, leaving nexts untouched.
@@ -410,161 +467,162 @@ impl<K: Ord + Debug, V: Debug> SkipList<K, V> {
         match self.next.take() {
             Some(mut node) => {
                 self.next = node.next.take();
+                if let Some(next) = self.next.as_mut() {
+                    next.prev = core::ptr::null_mut();
+                }
                 Some((node.key, node.value))
             }
             None => None,
         }
     }
 
-    // Replace next node with new node.
-    // Returns contents that was replaced.
-    // Caller must sort out the `nexts` before replacing next node.
-    // It will be pannic if there is no next node.
-    fn _replace_next(&mut self, mut node: Box<Node<K, V>>) -> Option<(K, V)> {
-        match self.next.take() {
-            Some(old) => {
-                node.next = old.next;
-                self.next = Some(node);
-                Some((old.key, old.value))
-            }
-            None => unreachable!(),
-        }
-    }
-
     // Insert a node right after.
     // Caller must sort out the `nexts` before inserting.
     fn _insert_next(&mut self, mut node: Box<Node<K, V>>) {
-        match self.next.take() {
-            Some(old) => {
-                node.next = Some(old);
-                self.next = Some(node);
-            }
-            None => {
-                node.next = None;
-                self.next = Some(node);
-            }
+        let node_ptr: *mut _ = &mut *node;
+        node.prev = core::ptr::null_mut();
+        node.next = self.next.take();
+        if let Some(next) = node.next.as_mut() {
+            next.prev = node_ptr;
         }
+        self.next = Some(node);
     }
 
-    fn _choose_level(&self, max: usize) -> usize {
-        let mut num = random::<usize>();
-        let mut level = 0;
-        while level < max {
-            if num & 1 == 1 {
-                break;
-            }
-            level += 1;
-            num >>= 1;
-        }
-        level
+    // The list grows one level at most for every insertion.
+    fn _choose_level(&mut self, max: usize) -> usize {
+        self.level_generator.random().min(max)
     }
 
-    pub fn insert(&mut self, k: K, v: V) -> Option<V> {
-        let mut level = self._choose_level(self.nexts.len());
+    // Link a new node right after `pres`, `pres` and `ranks` must be the
+    // result of `_get_pre_nodes` for `k`. They get the new top level too
+    // when the list grows. The finger, if any, is moved along.
+    fn _link(
+        &mut self,
+        pres: &mut Vec<*mut Node<K, V>>,
+        ranks: &mut Vec<usize>,
+        k: K,
+        v: V,
+    ) -> *mut Node<K, V> {
+        let level = self._choose_level(self.nexts.len());
         if level == self.nexts.len() {
-            self.nexts.push(std::ptr::null_mut())
+            self.nexts.push(core::ptr::null_mut());
+            self.widths.push(self.len + 1);
+            pres.push(core::ptr::null_mut());
+            ranks.push(0);
         }
 
+        let pos = ranks[0] + 1;
         let mut new_node = Box::new(Node::new(level + 1, k, v));
         let p_new_node: *mut _ = &mut *new_node;
 
-        let mut nexts = &mut self.nexts;
-        let mut pre = std::ptr::null_mut();
-        let mut equal = false;
-        loop {
-            if nexts[level].is_null() {
-                // If reach the end of the level, append the new node
-                // and go to the next level
-                nexts[level] = p_new_node;
+        for (l, (pre, rank)) in pres.iter().zip(ranks.iter()).enumerate() {
+            let (nexts, widths) = self._tower_of(*pre);
+            if l <= level {
+                new_node.nexts[l] = nexts[l];
+                new_node.widths[l] = widths[l] + 1 - (pos - rank);
+                nexts[l] = p_new_node;
+                widths[l] = pos - rank;
             } else {
-                // Current key to be compared
-                let tmp_key = unsafe { &(*nexts[level]).key };
-
-                match new_node.key.cmp(tmp_key) {
-                    Ordering::Greater => {
-                        // Record pre node for later use.
-                        pre = nexts[level];
-
-                        nexts = unsafe { &mut (*nexts[level]).nexts };
-
-                        continue;
-                    }
-                    Ordering::Equal => {
-                        // Replace old node with new node
-                        new_node.nexts[level] = unsafe { (*nexts[level]).nexts[level] };
-                        nexts[level] = p_new_node;
-
-                        equal = true;
-                    }
-                    Ordering::Less => {
-                        // Insert new node
-                        new_node.nexts[level] = nexts[level];
-                        nexts[level] = p_new_node;
-                    }
-                }
+                // Links above the new node skip one more node.
+                widths[l] += 1;
             }
+        }
 
-            if level == 0 {
-                break;
+        if pres[0].is_null() {
+            self._insert_next(new_node);
+        } else {
+            unsafe { (*pres[0])._insert_next(new_node) };
+        }
+        self.len += 1;
+
+        if let Some((tails, tail_ranks)) = &mut self.finger {
+            // Tails after the new node move one position, and the new node
+            // is the tail of the levels it ends.
+            if tails.len() < self.nexts.len() {
+                tails.push(core::ptr::null_mut());
+                tail_ranks.push(0);
+            }
+            for rank in tail_ranks.iter_mut().filter(|rank| **rank >= pos) {
+                *rank += 1;
+            }
+            let new_nexts = unsafe { &(*p_new_node).nexts };
+            for (l, next) in new_nexts.iter().enumerate() {
+                if next.is_null() {
+                    tails[l] = p_new_node;
+                    tail_ranks[l] = pos;
+                }
             }
-            level -= 1;
         }
 
-        let mut result = None;
+        p_new_node
+    }
 
-        if pre.is_null() {
-            if equal {
-                result = self._replace_next(new_node);
+    // Unlink `node` which is right after `pres`, `pres` must cover every
+    // level of the list.
+    // Returns contents that was removed.
+    fn _unlink(&mut self, pres: &[*mut Node<K, V>], node: *mut Node<K, V>) -> (K, V) {
+        self.finger = None;
+        let height = unsafe { (*node).nexts.len() };
+        for (l, pre) in pres.iter().enumerate() {
+            let (nexts, widths) = self._tower_of(*pre);
+            if l < height {
+                debug_assert!(nexts[l] == node);
+                unsafe {
+                    nexts[l] = (&(*node).nexts)[l];
+                    widths[l] += (&(*node).widths)[l] - 1;
+                }
             } else {
-                self._insert_next(new_node);
+                widths[l] -= 1;
             }
+        }
+
+        let result = if pres[0].is_null() {
+            self._remove_next()
         } else {
-            let pre_node = unsafe { &mut *pre };
+            unsafe { (*pres[0])._remove_next() }
+        };
+        self.len -= 1;
+        self._shrink();
 
-            if equal {
-                pre_node._replace_next(new_node);
-            } else {
-                pre_node._insert_next(new_node);
-            }
+        result.unwrap()
+    }
+
+    fn _get_first_node(&self) -> *mut Node<K, V> {
+        match self.nexts.first() {
+            Some(node) => *node,
+            None => core::ptr::null_mut(),
         }
+    }
 
-        result.map(|(_, v)| v)
+    // The node right after `pre` at level 0, null if there is none.
+    fn _next_node(&self, pre: *mut Node<K, V>) -> *mut Node<K, V> {
+        if pre.is_null() {
+            self._get_first_node()
+        } else {
+            unsafe { (&(*pre).nexts)[0] }
+        }
     }
 
-    #[allow(dead_code)]
-    pub fn remove<Q: ?Sized>(&mut self, q: &Q) -> Option<V>
-    where
-        K: Borrow<Q>,
-        Q: Ord,
-    {
-        if self.nexts.len() == 0 {
-            return None;
+    // The node right after `pres`, null if there is none.
+    fn _next_of(&self, pres: &[*mut Node<K, V>]) -> *mut Node<K, V> {
+        match pres.first() {
+            None => core::ptr::null_mut(),
+            Some(pre) => self._next_node(*pre),
         }
+    }
 
+    fn _get_last_node(&self) -> *mut Node<K, V> {
+        if self.nexts.is_empty() {
+            return core::ptr::null_mut();
+        }
+        let mut nexts = &self.nexts;
         let mut level = self.nexts.len() - 1;
-        let mut nexts = &mut self.nexts;
-        let mut pre = std::ptr::null_mut();
-        let mut equal = false;
+        let mut node = core::ptr::null_mut();
         loop {
             if !nexts[level].is_null() {
-                let tmp_key = unsafe { &(*nexts[level]).key };
-
-                match q.cmp(tmp_key.borrow()) {
-                    Ordering::Greater => {
-                        // Update pre node ptr, as the level goes down it will
-                        // finally reach the node just before the node to search.
-                        pre = nexts[level];
-
-                        nexts = unsafe { &mut (*nexts[level]).nexts };
-                        continue;
-                    }
-                    Ordering::Equal => {
-                        nexts[level] = unsafe { (*nexts[level]).nexts[level] };
-
-                        equal = true;
-                    }
-                    Ordering::Less => (),
-                }
+                node = nexts[level];
+                nexts = unsafe { &(*nexts[level]).nexts };
+                continue;
             }
             if level == 0 {
                 break;
@@ -572,43 +630,31 @@ impl<K: Ord + Debug, V: Debug> SkipList<K, V> {
             level -= 1;
         }
 
-        if !equal {
-            return None;
-        }
+        node
+    }
 
-        let result;
-        if pre.is_null() {
-            result = self._remove_next();
-        } else {
-            let pre_node = unsafe { &mut *pre };
-            result = pre_node._remove_next();
+    // The node at position `pos`, null if `pos` is 0 or out of range.
+    fn _get_node_at(&self, pos: usize) -> *mut Node<K, V> {
+        if pos == 0 || pos > self.len {
+            return core::ptr::null_mut();
         }
 
-        result.map(|(_, v)| v)
-    }
-
-    fn _get_node<Q: ?Sized>(&self, q: &Q) -> *mut Node<K, V>
-    where
-        K: Borrow<Q>,
-        Q: Ord,
-    {
         let mut nexts = &self.nexts;
+        let mut widths = &self.widths;
         let mut level = self.nexts.len() - 1;
-        let mut p_result = std::ptr::null_mut();
+        let mut rank = 0;
         loop {
-            if !nexts[level].is_null() {
-                let tmp_key = unsafe { &(*nexts[level]).key };
-                match q.cmp(tmp_key.borrow()) {
-                    Ordering::Greater => {
-                        nexts = unsafe { &(*nexts[level]).nexts };
-                        continue;
-                    }
-                    Ordering::Equal => {
-                        p_result = nexts[level];
-                        break;
-                    }
-                    Ordering::Less => (),
+            if !nexts[level].is_null() && rank + widths[level] <= pos {
+                let node = nexts[level];
+                rank += widths[level];
+                if rank == pos {
+                    return node;
                 }
+                unsafe {
+                    nexts = &(*node).nexts;
+                    widths = &(*node).widths;
+                }
+                continue;
             }
             if level == 0 {
                 break;
@@ -616,32 +662,443 @@ impl<K: Ord + Debug, V: Debug> SkipList<K, V> {
             level -= 1;
         }
 
-        p_result
+        unreachable!()
     }
 
-    fn _get_pre_node<Q: ?Sized>(&self, q: &Q) -> *mut Node<K, V>
-    where
-        K: Borrow<Q>,
-        Q: Ord + Debug,
-    {
-        if self.next.is_none() {
-            return std::ptr::null_mut();
+    // The last node of every level and their positions. A null pointer
+    // stands for the head.
+    fn _get_tail_nodes(&self) -> PreNodes<K, V> {
+        let mut tails = vec![core::ptr::null_mut(); self.nexts.len()];
+        let mut ranks = vec![0; self.nexts.len()];
+        let mut nexts = &self.nexts;
+        let mut widths = &self.widths;
+        let mut rank = 0;
+        for level in (0..self.nexts.len()).rev() {
+            while !nexts[level].is_null() {
+                rank += widths[level];
+                tails[level] = nexts[level];
+                unsafe {
+                    nexts = &(*tails[level]).nexts;
+                    widths = &(*tails[level]).widths;
+                }
+            }
+            if level > 0 {
+                tails[level - 1] = tails[level];
+            }
+            ranks[level] = rank;
+        }
+
+        (tails, ranks)
+    }
+
+    // Link a new node after every other node. `tails` and `ranks` must be
+    // the result of `_get_tail_nodes`, and are kept up to date.
+    // Only the links reaching the new node are touched, the widths of the
+    // links ending at the tail are left for `_close_tails`, which must be
+    // called once all nodes are pushed.
+    // Caller must make sure `k` is greater than every key in the list.
+    fn _push_back(
+        &mut self,
+        tails: &mut Vec<*mut Node<K, V>>,
+        ranks: &mut Vec<usize>,
+        k: K,
+        v: V,
+    ) {
+        self.finger = None;
+        let level = self._choose_level(self.nexts.len());
+        if level == self.nexts.len() {
+            self.nexts.push(core::ptr::null_mut());
+            self.widths.push(0);
+            tails.push(core::ptr::null_mut());
+            ranks.push(0);
+        }
+
+        let pos = self.len + 1;
+        let mut new_node = Box::new(Node::new(level + 1, k, v));
+        let p_new_node: *mut _ = &mut *new_node;
+        let last = tails[0];
+        if last.is_null() {
+            self._insert_next(new_node);
+        } else {
+            unsafe { (*last)._insert_next(new_node) };
+        }
+
+        for l in 0..=level {
+            let (nexts, widths) = self._tower_of(tails[l]);
+            nexts[l] = p_new_node;
+            widths[l] = pos - ranks[l];
+            tails[l] = p_new_node;
+            ranks[l] = pos;
+        }
+        self.len += 1;
+    }
+
+    // Fix the widths of the links ending at the tail after `_push_back`.
+    fn _close_tails(&mut self, tails: &[*mut Node<K, V>], ranks: &[usize]) {
+        let len = self.len;
+        for (l, (tail, rank)) in tails.iter().zip(ranks.iter()).enumerate() {
+            let (_, widths) = self._tower_of(*tail);
+            widths[l] = len + 1 - rank;
+        }
+    }
+}
+
+impl<K, V, C: Comparator<K>> SkipList<K, V, C> {
+    /// Removes and returns the last element in the list.
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        let last = self._get_last_node();
+        if last.is_null() {
+            return None;
+        }
+        let (pres, _) = self._get_pre_nodes(unsafe { &(*last).key }, false);
+        Some(self._unlink(&pres, last))
+    }
+
+    /// Constructs a double-ended iterator over a sub-range of entries in
+    /// the list.
+    ///
+    /// Panics if range `start > end` or if range `start == end` and both
+    /// bounds are `Excluded`.
+    pub fn range<T, R>(&self, range: R) -> Range<'_, K, V>
+    where
+        T: ?Sized,
+        C: Comparator<T>,
+        K: Borrow<T>,
+        R: RangeBounds<T>,
+    {
+        let (front, back, len) = self._range_bounds(&range);
+        Range::new(front, back, len)
+    }
+
+    /// Constructs a mutable double-ended iterator over a sub-range of
+    /// entries in the list.
+    ///
+    /// Panics if range `start > end` or if range `start == end` and both
+    /// bounds are `Excluded`.
+    pub fn range_mut<Q, R>(&mut self, range: R) -> RangeMut<'_, K, V>
+    where
+        Q: ?Sized,
+        C: Comparator<Q>,
+        K: Borrow<Q>,
+        R: RangeBounds<Q>,
+    {
+        let (front, back, len) = self._range_bounds(&range);
+        RangeMut::new(front, back, len)
+    }
+
+    /// Returns the number of entries inside `range` in `O(log n)`.
+    ///
+    /// Panics if range `start > end` or if range `start == end` and both
+    /// bounds are `Excluded`.
+    pub fn range_count<Q, R>(&self, range: R) -> usize
+    where
+        Q: ?Sized,
+        C: Comparator<Q>,
+        K: Borrow<Q>,
+        R: RangeBounds<Q>,
+    {
+        self._range_bounds(&range).2
+    }
+
+    /// Returns the position of `q` in the sorted order, starting from 0, or
+    /// `None` if the key is not in the list.
+    pub fn rank<Q>(&self, q: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let (pre, rank) = self._get_pre_node(q, false);
+        let current = self._next_node(pre);
+        if current.is_null() || self.comparator.compare(q, unsafe { (*current).key.borrow() }) != Ordering::Equal {
+            return None;
+        }
+        Some(rank)
+    }
+
+    /// Returns the entry with the greatest key not greater than `q`.
+    ///
+    /// ```
+    /// use skiplist::SkipList;
+    ///
+    /// let mut prices = SkipList::new();
+    /// prices.insert(100, 9.5);
+    /// prices.insert(200, 9.8);
+    /// // The latest price at or before time 150.
+    /// assert_eq!(prices.floor(&150), Some((&100, &9.5)));
+    /// assert_eq!(prices.floor(&50), None);
+    /// ```
+    pub fn floor<Q>(&self, q: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        self._entry(self._back_include_ptr(q).0)
+    }
+
+    /// Returns the entry with the least key not less than `q`.
+    pub fn ceiling<Q>(&self, q: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        self._entry(self._front_include_ptr(q).0)
+    }
+
+    /// Returns the entry with the greatest key less than `q`.
+    pub fn lower<Q>(&self, q: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        self._entry(self._back_exclude_ptr(q).0)
+    }
+
+    /// Returns the entry with the least key greater than `q`.
+    pub fn higher<Q>(&self, q: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        self._entry(self._front_exclude_ptr(q).0)
+    }
+
+    /// Returns the entry with the greatest key not greater than `q`, with a
+    /// mutable reference to the value.
+    pub fn floor_mut<Q>(&mut self, q: &Q) -> Option<(&K, &mut V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let node = self._back_include_ptr(q).0;
+        self._entry_mut(node)
+    }
+
+    /// Returns the entry with the least key not less than `q`, with a
+    /// mutable reference to the value.
+    pub fn ceiling_mut<Q>(&mut self, q: &Q) -> Option<(&K, &mut V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let node = self._front_include_ptr(q).0;
+        self._entry_mut(node)
+    }
+
+    /// Returns the entry with the greatest key less than `q`, with a mutable
+    /// reference to the value.
+    pub fn lower_mut<Q>(&mut self, q: &Q) -> Option<(&K, &mut V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let node = self._back_exclude_ptr(q).0;
+        self._entry_mut(node)
+    }
+
+    /// Returns the entry with the least key greater than `q`, with a mutable
+    /// reference to the value.
+    pub fn higher_mut<Q>(&mut self, q: &Q) -> Option<(&K, &mut V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let node = self._front_exclude_ptr(q).0;
+        self._entry_mut(node)
+    }
+
+    // The entry of `node`, `None` if it is null.
+    fn _entry(&self, node: *mut Node<K, V>) -> Option<(&K, &V)> {
+        if node.is_null() {
+            return None;
+        }
+        let node = unsafe { &*node };
+        Some((&node.key, &node.value))
+    }
+
+    fn _entry_mut(&mut self, node: *mut Node<K, V>) -> Option<(&K, &mut V)> {
+        if node.is_null() {
+            return None;
+        }
+        let node = unsafe { &mut *node };
+        Some((&node.key, &mut node.value))
+    }
+
+    // Find the first and the last node inside `range`, and the number of
+    // nodes between them. Both are null if there is no node in the range.
+    fn _range_bounds<Q, R>(&self, range: &R) -> (*mut Node<K, V>, *mut Node<K, V>, usize)
+    where
+        Q: ?Sized,
+        C: Comparator<Q>,
+        K: Borrow<Q>,
+        R: RangeBounds<Q>,
+    {
+        _check_range(range, &self.comparator);
+
+        let (front, front_pos) = match range.start_bound() {
+            Bound::Unbounded => (self._get_first_node(), 1),
+            Bound::Included(key) => self._front_include_ptr(key),
+            Bound::Excluded(key) => self._front_exclude_ptr(key),
+        };
+
+        let (back, back_pos) = match range.end_bound() {
+            Bound::Unbounded => (self._get_last_node(), self.len),
+            Bound::Included(key) => self._back_include_ptr(key),
+            Bound::Excluded(key) => self._back_exclude_ptr(key),
+        };
+
+        if front.is_null() || back.is_null() || back_pos < front_pos {
+            return (core::ptr::null_mut(), core::ptr::null_mut(), 0);
+        }
+
+        (front, back, back_pos - front_pos + 1)
+    }
+
+    // First node not less than `key`, and its position.
+    fn _front_include_ptr<Q>(&self, key: &Q) -> (*mut Node<K, V>, usize)
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let (pre, rank) = self._get_pre_node(key, false);
+        (self._next_node(pre), rank + 1)
+    }
+
+    // First node greater than `key`, and its position.
+    fn _front_exclude_ptr<Q>(&self, key: &Q) -> (*mut Node<K, V>, usize)
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let (pre, rank) = self._get_pre_node(key, true);
+        (self._next_node(pre), rank + 1)
+    }
+
+    // Last node not greater than `key`, and its position.
+    fn _back_include_ptr<Q>(&self, key: &Q) -> (*mut Node<K, V>, usize)
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        self._get_pre_node(key, true)
+    }
+
+    // Last node less than `key`, and its position.
+    fn _back_exclude_ptr<Q>(&self, key: &Q) -> (*mut Node<K, V>, usize)
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        self._get_pre_node(key, false)
+    }
+
+    pub fn insert(&mut self, k: K, v: V) -> Option<V> {
+        let (mut pres, mut ranks) = match self._take_finger(&k) {
+            Some(finger) => finger,
+            None => self._get_pre_nodes(&k, false),
+        };
+        let current = self._next_of(&pres);
+        if !current.is_null() {
+            let node = unsafe { &mut *current };
+            if self.comparator.compare(&node.key, &k) == Ordering::Equal {
+                return Some(core::mem::replace(&mut node.value, v));
+            }
+        }
+
+        let node = self._link(&mut pres, &mut ranks, k, v);
+        let node_ref = unsafe { &*node };
+        if node_ref.nexts[0].is_null() {
+            // Appended, the pre nodes of a greater key are the tails.
+            for l in 0..node_ref.nexts.len() {
+                pres[l] = node;
+                ranks[l] = self.len;
+            }
+            self.finger = Some((pres, ranks));
+        }
+        None
+    }
+
+    // The finger recorded by the last `insert`, if it can be used for `k`.
+    // Otherwise it stays for the inserts after.
+    fn _take_finger(&mut self, k: &K) -> Option<PreNodes<K, V>> {
+        let (pres, _) = self.finger.as_ref()?;
+        let last = unsafe { &(*pres[0]).key };
+        if self.comparator.compare(last, k) == Ordering::Less {
+            self.finger.take()
+        } else {
+            None
+        }
+    }
+
+    /// Removes a key from the list, returning the value at the key if the
+    /// key was previously in the list.
+    pub fn remove<Q>(&mut self, q: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        self.remove_entry(q).map(|(_, v)| v)
+    }
+
+    /// Removes a key from the list, returning the stored key and value if
+    /// the key was previously in the list.
+    pub fn remove_entry<Q>(&mut self, q: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let (pres, _) = self._get_pre_nodes(q, false);
+        let current = self._next_of(&pres);
+        if current.is_null() {
+            return None;
+        }
+        if self.comparator.compare(q, unsafe { (*current).key.borrow() }) != Ordering::Equal {
+            return None;
+        }
+
+        Some(self._unlink(&pres, current))
+    }
+
+    fn _get_node<Q>(&self, q: &Q) -> *mut Node<K, V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        if self.nexts.is_empty() {
+            return core::ptr::null_mut();
         }
 
         let mut nexts = &self.nexts;
         let mut level = self.nexts.len() - 1;
-        let mut pre = std::ptr::null_mut();
+        let mut p_result = core::ptr::null_mut();
         loop {
             if !nexts[level].is_null() {
                 let tmp_key = unsafe { &(*nexts[level]).key };
-                // println!("get_pre_node: q: {:?}, tmp_key: {:?}", q, tmp_key);
-                match q.cmp(tmp_key.borrow()) {
+                match self.comparator.compare(q, tmp_key.borrow()) {
                     Ordering::Greater => {
-                        pre = nexts[level];
                         nexts = unsafe { &(*nexts[level]).nexts };
-                        continue
-                    },
-                    Ordering::Equal | Ordering::Less => (),
+                        continue;
+                    }
+                    Ordering::Equal => {
+                        p_result = nexts[level];
+                        break;
+                    }
+                    Ordering::Less => (),
                 }
             }
             if level == 0 {
@@ -650,133 +1107,795 @@ impl<K: Ord + Debug, V: Debug> SkipList<K, V> {
             level -= 1;
         }
 
-        // println!("get_pre_node result: q: {:?}, result: {:?}", q, unsafe { &(&*pre).key });
-
-        pre
+        p_result
     }
 
-    fn _get_last_node(&self) -> *mut Node<K, V> {
-        if self.nexts.len() == 0 {
-            return std::ptr::null_mut();
+    // Last node whose key is less than `q`, or not greater than `q` if
+    // `inclusive`, and its position. A null pointer stands for the head.
+    fn _get_pre_node<Q>(&self, q: &Q, inclusive: bool) -> (*mut Node<K, V>, usize)
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let mut pre = core::ptr::null_mut();
+        let mut rank = 0;
+        if self.nexts.is_empty() {
+            return (pre, rank);
         }
+
         let mut nexts = &self.nexts;
+        let mut widths = &self.widths;
         let mut level = self.nexts.len() - 1;
-        let mut node = std::ptr::null_mut();
         loop {
             if !nexts[level].is_null() {
-                node = nexts[level];
-                nexts = unsafe { &(*nexts[level]).nexts };
+                let tmp_key = unsafe { &(*nexts[level]).key };
+                match self.comparator.compare(q, tmp_key.borrow()) {
+                    Ordering::Greater => (),
+                    Ordering::Equal if inclusive => (),
+                    Ordering::Equal | Ordering::Less => {
+                        if level == 0 {
+                            break;
+                        }
+                        level -= 1;
+                        continue;
+                    }
+                }
+                rank += widths[level];
+                pre = nexts[level];
+                unsafe {
+                    nexts = &(*pre).nexts;
+                    widths = &(*pre).widths;
+                }
                 continue;
             }
             if level == 0 {
                 break;
             }
-            level -= 1;
+            level -= 1;
+        }
+
+        (pre, rank)
+    }
+
+    // Same as `_get_pre_node`, but records the pre node of every level and
+    // their positions. A null pointer in the result stands for the head.
+    fn _get_pre_nodes<Q>(&self, q: &Q, inclusive: bool) -> PreNodes<K, V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let mut pres = vec![core::ptr::null_mut(); self.nexts.len()];
+        let mut ranks = vec![0; self.nexts.len()];
+        if self.nexts.is_empty() {
+            return (pres, ranks);
+        }
+
+        let mut nexts = &self.nexts;
+        let mut widths = &self.widths;
+        let mut level = self.nexts.len() - 1;
+        let mut pre = core::ptr::null_mut();
+        let mut rank = 0;
+        loop {
+            if !nexts[level].is_null() {
+                let tmp_key = unsafe { &(*nexts[level]).key };
+                let order = self.comparator.compare(q, tmp_key.borrow());
+                if order == Ordering::Greater || (inclusive && order == Ordering::Equal) {
+                    rank += widths[level];
+                    pre = nexts[level];
+                    unsafe {
+                        nexts = &(*pre).nexts;
+                        widths = &(*pre).widths;
+                    }
+                    continue;
+                }
+            }
+            pres[level] = pre;
+            ranks[level] = rank;
+            if level == 0 {
+                break;
+            }
+            level -= 1;
+        }
+
+        (pres, ranks)
+    }
+
+    pub fn get<Q>(&self, q: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let p_result = self._get_node(q);
+
+        if !p_result.is_null() {
+            let result_node = unsafe { &*p_result };
+            Some(&result_node.value)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut<Q>(&mut self, q: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let p_result = self._get_node(q);
+
+        if !p_result.is_null() {
+            let result_node = unsafe { &mut *p_result };
+            Some(&mut result_node.value)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if the list contains a value for the specified key.
+    pub fn contains_key<Q>(&self, q: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        !self._get_node(q).is_null()
+    }
+
+    /// Removes every entry inside `range` and returns how many were removed.
+    ///
+    /// The run of nodes is unlinked by fixing each level once, so it takes
+    /// `O(log n)` plus dropping the entries.
+    ///
+    /// Panics if range `start > end` or if range `start == end` and both
+    /// bounds are `Excluded`.
+    pub fn remove_range<Q, R>(&mut self, range: R) -> usize
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+        R: RangeBounds<Q>,
+    {
+        self._cut_range(&range).len()
+    }
+
+    /// Removes every entry inside `range` and returns them in an owning
+    /// iterator, see `remove_range`. The entries are out of the list even
+    /// if the iterator is not consumed.
+    ///
+    /// Panics if range `start > end` or if range `start == end` and both
+    /// bounds are `Excluded`.
+    pub fn drain_range<Q, R>(&mut self, range: R) -> IntoIter<K, V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+        R: RangeBounds<Q>,
+    {
+        self._cut_range(&range)
+    }
+
+    // Unlink the nodes inside `range` as a whole.
+    fn _cut_range<Q, R>(&mut self, range: &R) -> IntoIter<K, V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+        R: RangeBounds<Q>,
+    {
+        _check_range(range, &self.comparator);
+
+        // Pre nodes of the first node inside the range, and the last nodes
+        // of every level not after the range.
+        let (pres, ranks) = match range.start_bound() {
+            Bound::Unbounded => (
+                vec![core::ptr::null_mut(); self.nexts.len()],
+                vec![0; self.nexts.len()],
+            ),
+            Bound::Included(key) => self._get_pre_nodes(key, false),
+            Bound::Excluded(key) => self._get_pre_nodes(key, true),
+        };
+        let (lasts, last_ranks) = match range.end_bound() {
+            Bound::Unbounded => self._get_tail_nodes(),
+            Bound::Included(key) => self._get_pre_nodes(key, true),
+            Bound::Excluded(key) => self._get_pre_nodes(key, false),
+        };
+        let count = match (ranks.first(), last_ranks.first()) {
+            (Some(first), Some(last)) if last > first => last - first,
+            _ => return IntoIter { next: None, back: core::ptr::null_mut(), len: 0 },
+        };
+
+        self.finger = None;
+        for l in 0..pres.len() {
+            if last_ranks[l] > ranks[l] {
+                // The level has nodes inside the range, skip them.
+                let (next, width) = {
+                    let (nexts, widths) = self._tower_of(lasts[l]);
+                    (nexts[l], widths[l])
+                };
+                let (nexts, widths) = self._tower_of(pres[l]);
+                nexts[l] = next;
+                widths[l] = last_ranks[l] + width - count - ranks[l];
+            } else {
+                let (_, widths) = self._tower_of(pres[l]);
+                widths[l] -= count;
+            }
+        }
+
+        let (pre, back) = (pres[0], lasts[0]);
+        let rest = unsafe { (*back).next.take() };
+        let mut cut = if pre.is_null() {
+            core::mem::replace(&mut self.next, rest)
+        } else {
+            unsafe { core::mem::replace(&mut (*pre).next, rest) }
+        };
+        if let Some(first) = cut.as_mut() {
+            first.prev = core::ptr::null_mut();
+        }
+        let next = if pre.is_null() {
+            self.next.as_mut()
+        } else {
+            unsafe { (*pre).next.as_mut() }
+        };
+        if let Some(next) = next {
+            next.prev = pre;
+        }
+        self.len -= count;
+        self._shrink();
+
+        IntoIter { next: cut, back, len: count }
+    }
+
+    /// Splits the list into two at the given key. Returns everything after
+    /// the given key, including the key.
+    ///
+    /// Only the links crossing the split point are rewired, so it takes
+    /// `O(log n)`. The returned list gets a fork of the level generator and
+    /// a clone of the comparator.
+    pub fn split_off<Q>(&mut self, q: &Q) -> SkipList<K, V, C>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q> + Clone,
+    {
+        let mut other = SkipList::_with_parts(self.comparator.clone(), self.level_generator.fork());
+        self.finger = None;
+        let (pres, ranks) = self._get_pre_nodes(q, false);
+        let pos = match ranks.first() {
+            Some(rank) => *rank,
+            None => return other,
+        };
+        if pos == self.len {
+            return other;
+        }
+
+        for (l, (pre, rank)) in pres.iter().zip(ranks.iter()).enumerate() {
+            let (nexts, widths) = self._tower_of(*pre);
+            other.nexts.push(nexts[l]);
+            other.widths.push(rank + widths[l] - pos);
+            nexts[l] = core::ptr::null_mut();
+            widths[l] = pos + 1 - rank;
+        }
+
+        let pre = pres[0];
+        let mut next = if pre.is_null() {
+            self.next.take()
+        } else {
+            unsafe { (*pre).next.take() }
+        };
+        if let Some(first) = next.as_mut() {
+            first.prev = core::ptr::null_mut();
+        }
+        other.next = next;
+        other.len = self.len - pos;
+        self.len = pos;
+
+        self._shrink();
+        other._shrink();
+        other
+    }
+
+    /// Moves all elements from `other` into `self`, leaving `other` empty.
+    /// If a key from `other` is already present in `self`, the value from
+    /// `self` is overwritten.
+    ///
+    /// When every key of `other` is greater than the keys of `self`, the two
+    /// lists are concatenated in `O(log n)`. Otherwise they are merged in
+    /// linear time.
+    pub fn append(&mut self, other: &mut SkipList<K, V, C>) {
+        let ordered = match (self.last_key_value(), other.first_key_value()) {
+            (Some((last, _)), Some((first, _))) => {
+                self.comparator.compare(last, first) == Ordering::Less
+            }
+            _ => true,
+        };
+        if ordered {
+            self._concat(other);
+        } else {
+            self._merge(other);
+        }
+    }
+
+    // Move the nodes of `other` after the nodes of `self`.
+    // Caller must make sure the keys of `other` are greater than the keys
+    // of `self`.
+    fn _concat(&mut self, other: &mut SkipList<K, V, C>) {
+        self.finger = None;
+        other.finger = None;
+        let (tails, ranks) = self._get_tail_nodes();
+        let height = self.nexts.len().max(other.nexts.len());
+        for l in 0..height {
+            // Position of the node following the tail in the new list.
+            let target = self.len + other.widths.get(l).copied().unwrap_or(other.len + 1);
+            let next = other.nexts.get(l).copied().unwrap_or(core::ptr::null_mut());
+            if l < tails.len() {
+                let (nexts, widths) = self._tower_of(tails[l]);
+                nexts[l] = next;
+                widths[l] = target - ranks[l];
+            } else {
+                self.nexts.push(next);
+                self.widths.push(target);
+            }
+        }
+
+        let mut next = other.next.take();
+        if let Some(first) = next.as_mut() {
+            first.prev = tails.first().copied().unwrap_or(core::ptr::null_mut());
+        }
+        match tails.first() {
+            Some(tail) if !tail.is_null() => unsafe { (**tail).next = next },
+            _ => self.next = next,
+        }
+        self.len += other.len;
+
+        other.nexts.clear();
+        other.widths.clear();
+        other.len = 0;
+    }
+
+    // Rebuild `self` from the merged entries of both lists, `other` wins on
+    // equal keys.
+    fn _merge(&mut self, other: &mut SkipList<K, V, C>) {
+        let mut a = self._take_all().peekable();
+        let mut b = other._take_all().peekable();
+        let (mut tails, mut ranks) = (vec![], vec![]);
+        loop {
+            let order = match (a.peek(), b.peek()) {
+                (Some(x), Some(y)) => self.comparator.compare(&x.0, &y.0),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => break,
+            };
+            let (k, v) = match order {
+                Ordering::Less => a.next().unwrap(),
+                Ordering::Greater => b.next().unwrap(),
+                Ordering::Equal => {
+                    a.next();
+                    b.next().unwrap()
+                }
+            };
+            self._push_back(&mut tails, &mut ranks, k, v);
+        }
+        self._close_tails(&tails, &ranks);
+    }
+
+    // Push sorted entries after every other node, see `from_sorted_iter`.
+    // An entry equal to the last node replaces its value.
+    fn _extend_sorted<I>(&mut self, iter: I) -> Result<(), SkipListError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let (mut tails, mut ranks) = self._get_tail_nodes();
+        let mut result = Ok(());
+        for (index, (k, v)) in iter.into_iter().enumerate() {
+            if let Some(last) = tails.first().filter(|last| !last.is_null()) {
+                let last = unsafe { &mut **last };
+                match self.comparator.compare(&k, &last.key) {
+                    Ordering::Less => {
+                        result = Err(SkipListError::UnsortedKey(index));
+                        break;
+                    }
+                    Ordering::Equal => {
+                        last.value = v;
+                        continue;
+                    }
+                    Ordering::Greater => (),
+                }
+            }
+            self._push_back(&mut tails, &mut ranks, k, v);
         }
-
-        node
+        self._close_tails(&tails, &ranks);
+        result
     }
 
-    #[allow(dead_code)]
-    pub fn get<Q: ?Sized>(&self, q: &Q) -> Option<&V>
+    /// Builds a list ordered by `comparator` from entries sorted by it, see
+    /// `from_sorted_iter`.
+    pub fn from_sorted_iter_with_comparator<I>(
+        iter: I,
+        comparator: C,
+    ) -> Result<SkipList<K, V, C>, SkipListError>
     where
-        K: Borrow<Q>,
-        Q: Ord,
+        I: IntoIterator<Item = (K, V)>,
     {
-        let p_result = self._get_node(q);
-
-        if !p_result.is_null() {
-            let result_node = unsafe { &*p_result };
-            Some(&result_node.value)
-        } else {
-            None
-        }
+        let mut list = SkipList::with_comparator(comparator);
+        list._extend_sorted(iter)?;
+        Ok(list)
     }
+}
 
-    #[allow(dead_code)]
-    pub fn get_mut<Q: ?Sized>(&mut self, q: &Q) -> Option<&mut V>
+impl<K: Ord, V> SkipList<K, V> {
+    /// Builds a list from entries sorted by key in one pass, without
+    /// searching.
+    ///
+    /// Entries with equal keys are deduplicated, the last one wins. Returns
+    /// `SkipListError::UnsortedKey` if a key is less than the key before it.
+    pub fn from_sorted_iter<I>(iter: I) -> Result<SkipList<K, V>, SkipListError>
     where
-        K: Borrow<Q>,
-        Q: Ord,
+        I: IntoIterator<Item = (K, V)>,
     {
-        let p_result = self._get_node(q);
+        SkipList::from_sorted_iter_with_comparator(iter, OrdComparator)
+    }
+}
 
-        if !p_result.is_null() {
-            let result_node = unsafe { &mut *p_result };
-            Some(&mut result_node.value)
-        } else {
-            None
-        }
+impl<K, V, C> Drop for SkipList<K, V, C> {
+    fn drop(&mut self) {
+        self._drop_chain();
     }
+}
 
-    pub fn iter<'a>(&'a self) -> Iter<'a, K, V> {
-        Iter {
-            next: self.next.as_ref().map(|node| &**node),
+impl<K, V, C: Default> Default for SkipList<K, V, C> {
+    fn default() -> SkipList<K, V, C> {
+        SkipList::with_comparator(C::default())
+    }
+}
+
+impl<K, V, C: Comparator<K> + Default> FromIterator<(K, V)> for SkipList<K, V, C> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> SkipList<K, V, C> {
+        // The sort is stable, so the last of equal keys wins like `insert`.
+        let mut entries: Vec<_> = iter.into_iter().collect();
+        let mut list = SkipList::with_comparator(C::default());
+        let comparator = &list.comparator;
+        entries.sort_by(|a, b| comparator.compare(&a.0, &b.0));
+        list._extend_sorted(entries).expect("entries are sorted");
+        list
+    }
+}
+
+impl<K, V, C: Comparator<K>> Extend<(K, V)> for SkipList<K, V, C> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        // Increasing keys take the finger path of `insert`.
+        for (k, v) in iter {
+            self.insert(k, v);
         }
     }
+}
 
-    pub fn iter_mut<'a>(&'a mut self) -> IterMut<'a, K, V> {
-        IterMut {
-            next: self.next.as_mut().map(|node| &mut **node),
+/// Rebuilds the towers with a fork of the level generator.
+impl<K: Clone, V: Clone, C: Comparator<K> + Clone> Clone for SkipList<K, V, C> {
+    fn clone(&self) -> SkipList<K, V, C> {
+        let mut list = SkipList::_with_parts(self.comparator.clone(), self.level_generator.fork());
+        list._extend_sorted(self.iter().map(|(k, v)| (k.clone(), v.clone())))
+            .expect("entries of a list are sorted");
+        list
+    }
+}
+
+impl<K: PartialEq, V: PartialEq, C> PartialEq for SkipList<K, V, C> {
+    fn eq(&self, other: &SkipList<K, V, C>) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<K: Eq, V: Eq, C> Eq for SkipList<K, V, C> {}
+
+impl<K: PartialOrd, V: PartialOrd, C> PartialOrd for SkipList<K, V, C> {
+    fn partial_cmp(&self, other: &SkipList<K, V, C>) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<K: Ord, V: Ord, C> Ord for SkipList<K, V, C> {
+    fn cmp(&self, other: &SkipList<K, V, C>) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl<K: Hash, V: Hash, C> Hash for SkipList<K, V, C> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.len);
+        for entry in self.iter() {
+            entry.hash(state);
         }
     }
+}
+
+impl<K: Debug, V: Debug, C> Debug for SkipList<K, V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, Q, V, C> Index<&Q> for SkipList<K, V, C>
+where
+    K: Borrow<Q>,
+    Q: ?Sized,
+    C: Comparator<K> + Comparator<Q>,
+{
+    type Output = V;
+
+    /// Returns the value of `q`.
+    ///
+    /// Panics if `q` is not in the list.
+    fn index(&self, q: &Q) -> &V {
+        self.get(q).expect("no entry found for key")
+    }
+}
 
-    pub fn into_iter(self) -> IntoIter<K, V> {
-        IntoIter(self)
+// Same checks as `BTreeMap::range`.
+pub(crate) fn _check_range<Q, R, C>(range: &R, comparator: &C)
+where
+    Q: ?Sized,
+    R: RangeBounds<Q>,
+    C: Comparator<Q>,
+{
+    match (range.start_bound(), range.end_bound()) {
+        (Bound::Excluded(s), Bound::Excluded(e))
+            if comparator.compare(s, e) == Ordering::Equal =>
+        {
+            panic!("range start and end are equal and excluded in SkipList")
+        }
+        (Bound::Included(s), Bound::Included(e))
+        | (Bound::Included(s), Bound::Excluded(e))
+        | (Bound::Excluded(s), Bound::Included(e))
+        | (Bound::Excluded(s), Bound::Excluded(e))
+            if comparator.compare(s, e) == Ordering::Greater =>
+        {
+            panic!("range start is greater than range end in SkipList")
+        }
+        _ => {}
     }
 }
 
-struct Iter<'a, K, V> {
-    next: Option<&'a Node<K, V>>,
+/// An iterator over the entries of a `SkipList`.
+pub struct Iter<'a, K, V> {
+    range: Range<'a, K, V>,
 }
 
 impl<'a, K, V> Iterator for Iter<'a, K, V> {
     type Item = (&'a K, &'a V);
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.next.map(|node| {
-            self.next = node.next.as_ref().map(|node| &**node);
-            (&node.key, &node.value)
-        })
+        self.range.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.range.nth(n)
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.range.next_back()
     }
 }
 
-struct IterMut<'a, K, V> {
-    next: Option<&'a mut Node<K, V>>,
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+impl<'a, K, V> FusedIterator for Iter<'a, K, V> {}
+
+/// A mutable iterator over the entries of a `SkipList`.
+pub struct IterMut<'a, K, V> {
+    range: RangeMut<'a, K, V>,
 }
 
 impl<'a, K, V> Iterator for IterMut<'a, K, V> {
     type Item = (&'a K, &'a mut V);
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.next.take().map(|node| {
-            self.next = node.next.as_mut().map(|node| &mut **node);
-            (&node.key, &mut node.value)
-        })
+        self.range.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.range.nth(n)
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for IterMut<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.range.next_back()
     }
 }
 
-pub struct IntoIter<K, V>(SkipList<K, V>);
+impl<'a, K, V> ExactSizeIterator for IterMut<'a, K, V> {}
 
-impl<K: Ord, V> Iterator for IntoIter<K, V> {
+impl<'a, K, V> FusedIterator for IterMut<'a, K, V> {}
+
+/// An owning iterator over the entries of a `SkipList`.
+pub struct IntoIter<K, V> {
+    // Only the `next` chain of the list is kept, the towers are dropped.
+    next: Option<Box<Node<K, V>>>,
+    back: *mut Node<K, V>,
+    len: usize,
+}
+
+// `back` points into the chain owned by `next`.
+unsafe impl<K: Send, V: Send> Send for IntoIter<K, V> {}
+
+unsafe impl<K: Sync, V: Sync> Sync for IntoIter<K, V> {}
+
+impl<K, V> IntoIter<K, V> {
+    // Remove the first node of the chain.
+    fn _remove_first(&mut self) -> Option<(K, V)> {
+        let mut node = self.next.take()?;
+        self.next = node.next.take();
+        if let Some(next) = self.next.as_mut() {
+            next.prev = core::ptr::null_mut();
+        }
+        Some((node.key, node.value))
+    }
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
     type Item = (K, V);
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.0.next.take() {
-            Some(mut node) => {
-                self.0.next = node.next.take();
-                Some((node.key, node.value))
-            },
-            None => None,
+        let result = self._remove_first()?;
+        self.len -= 1;
+        if self.len == 0 {
+            self.back = core::ptr::null_mut();
+        }
+        Some(result)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.back.is_null() {
+            return None;
+        }
+
+        let pre = unsafe { (*self.back).prev };
+        let result = if pre.is_null() {
+            self._remove_first()
+        } else {
+            unsafe { (*pre)._remove_next() }
+        };
+        self.back = pre;
+        self.len -= 1;
+        result
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+impl<K, V> Drop for IntoIter<K, V> {
+    fn drop(&mut self) {
+        Node::_drop_chain(self.next.take());
+    }
+}
+
+impl<K, V> FusedIterator for IntoIter<K, V> {}
+
+impl<K, V, C> IntoIterator for SkipList<K, V, C> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(mut self) -> IntoIter<K, V> {
+        self._take_all()
+    }
+}
+
+impl<'a, K, V, C> IntoIterator for &'a SkipList<K, V, C> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, C> IntoIterator for &'a mut SkipList<K, V, C> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> IterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+/// An iterator removing the entries of a `SkipList` which match a
+/// predicate, created by `extract_if`.
+pub struct ExtractIf<'a, K, V, F, C = OrdComparator> {
+    cursor: CursorMut<'a, K, V, C>,
+    pred: F,
+}
+
+impl<'a, K, V, F, C> Iterator for ExtractIf<'a, K, V, F, C>
+where
+    F: FnMut(&K, &mut V) -> bool,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (k, v) = self.cursor._current_mut()?;
+            if (self.pred)(k, v) {
+                return self.cursor.remove_current();
+            }
+            self.cursor.move_next();
         }
     }
 }
 
+impl<'a, K, V, F, C> FusedIterator for ExtractIf<'a, K, V, F, C> where
+    F: FnMut(&K, &mut V) -> bool
+{
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::comparator::ReverseComparator;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeMap;
+    use std::format;
+    use std::string::{String, ToString};
+
+    fn heights<K, V, C>(sk: &SkipList<K, V, C>) -> Vec<usize> {
+        let mut heights = vec![];
+        let mut next = sk.next.as_deref();
+        while let Some(node) = next {
+            heights.push(node.nexts.len());
+            next = node.next.as_deref();
+        }
+        heights
+    }
+
+    // Check that every width matches the distance between the linked nodes.
+    pub(super) fn check_widths<K, V, C>(sk: &SkipList<K, V, C>) {
+        let mut positions = std::collections::HashMap::new();
+        let mut next = sk.next.as_deref();
+        let mut pos = 1;
+        while let Some(node) = next {
+            positions.insert(node as *const Node<K, V>, pos);
+            next = node.next.as_deref();
+            pos += 1;
+        }
+        let check = |nexts: &Vec<*mut Node<K, V>>, widths: &Vec<usize>, from: usize| {
+            assert_eq!(nexts.len(), widths.len());
+            for (next, width) in nexts.iter().zip(widths) {
+                let to = if next.is_null() {
+                    sk.len + 1
+                } else {
+                    positions[&(*next as *const Node<K, V>)]
+                };
+                assert_eq!(*width, to - from);
+            }
+        };
+        check(&sk.nexts, &sk.widths, 0);
+        let mut next = sk.next.as_deref();
+        while let Some(node) = next {
+            check(&node.nexts, &node.widths, positions[&(node as *const Node<K, V>)]);
+            next = node.next.as_deref();
+        }
+    }
 
-    // #[test]
+    #[test]
     fn skiplist_basic() {
         let mut sk = SkipList::new();
         sk.insert("aa".to_string(), "aa1".to_string());
@@ -790,45 +1909,28 @@ mod tests {
         let ab = "ab".to_string();
         let dd = "dd".to_string();
         let cc = "cc".to_string();
-        println!("a: {:?}", sk.get(&a));
-        println!("aa: {:?}", sk.get(&aa));
-        println!("ab: {:?}", sk.get(&ab));
-        println!("dd: {:?}", sk.get(&dd));
-        println!("cc: {:?}", sk.get(&cc));
-        println!("aa: {:?}", sk.get_mut(&aa));
-        println!("ab: {:?}", sk.get_mut(&ab));
-        println!("dd: {:?}", sk.get_mut(&dd));
-        println!("cc: {:?}", sk.get_mut(&cc));
-
-        let r_ab = sk.remove(&ab);
-        println!("r_ab: {:?}", r_ab);
-        let r_ab = sk.remove(&ab);
-        println!("r_ab: {:?}", r_ab);
-        let r_dd = sk.remove(&dd);
-        println!("r_dd: {:?}", r_dd);
-        println!("dd: {:?}", sk.get(&dd));
-
-        match sk.get_mut(&dd) {
-            None => (),
-            Some(ddv) => *ddv = "dd2".to_string(),
-        };
-
-        let a = sk.get_mut(&dd);
-        match a {
-            None => (),
-            Some(ddv) => *ddv = "dd3".to_string(),
-        };
-
-        let b = sk.get_mut(&dd);
-        match b {
-            None => (),
-            Some(ddv) => *ddv = "dd4".to_string(),
-        };
-
-        println!("dd: {:?}", sk.get_mut(&dd));
+        assert_eq!(sk.get(&a).map(|s| s.as_str()), Some("a1"));
+        assert_eq!(sk.get(&aa).map(|s| s.as_str()), Some("aa2"));
+        assert_eq!(sk.get(&ab).map(|s| s.as_str()), Some("ab1"));
+        assert_eq!(sk.get(&dd).map(|s| s.as_str()), Some("dd1"));
+        assert_eq!(sk.get(&cc), None);
+        assert_eq!(sk.get_mut(&aa).map(|s| s.as_str()), Some("aa2"));
+        assert_eq!(sk.get_mut(&cc), None);
+
+        assert_eq!(sk.remove(&ab), Some("ab1".to_string()));
+        assert_eq!(sk.remove(&ab), None);
+        assert_eq!(sk.remove(&dd), Some("dd1".to_string()));
+        assert_eq!(sk.get(&dd), None);
+        assert_eq!(sk.get_mut(&dd), None);
+
+        if let Some(v) = sk.get_mut(&a) {
+            *v = "a2".to_string();
+        }
+        assert_eq!(sk.get("a"), Some(&"a2".to_string()));
+        assert_eq!(sk.len(), 2);
     }
 
-    // #[test]
+    #[test]
     fn iter() {
         let mut sk = SkipList::new();
         sk.insert("aa".to_string(), "aa1".to_string());
@@ -837,22 +1939,16 @@ mod tests {
         sk.insert("aa".to_string(), "aa2".to_string());
         sk.insert("a".to_string(), "a1".to_string());
 
-        for (k, v) in sk.iter() {
-            println!("--- {}: {}", k, v);
-        }
+        let keys: Vec<_> = sk.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, vec!["a", "aa", "ab", "dd"]);
 
-        for (k, v) in sk.iter_mut() {
-            println!("---2 {}: {}", k, v);
+        for (_, v) in sk.iter_mut() {
             *v = "hhh".to_string();
         }
+        assert!(sk.iter().all(|(_, v)| v == "hhh"));
 
-        for (k, v) in sk.iter() {
-            println!("---3 {}: {}", k, v);
-        }
-
-        for (k, v) in sk.into_iter() {
-            println!("---4 {}: {}", k, v);
-        }
+        let pairs: Vec<_> = sk.into_iter().map(|(k, _)| k).collect();
+        assert_eq!(pairs, vec!["a", "aa", "ab", "dd"]);
     }
 
     #[test]
@@ -865,84 +1961,645 @@ mod tests {
         sk.insert(22, 22);
         sk.insert(32, 32);
 
-        println!("start iter");
-        for (k, v) in sk.iter() {
-            println!("iter_kv {}: {}", k, v)
+        let keys = |r: Range<'_, i32, i32>| r.map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys(sk.range(15..31)), vec![15, 22]);
+        assert_eq!(keys(sk.range(15..32)), vec![15, 22]);
+        assert_eq!(keys(sk.range(15..=32)), vec![15, 22, 32]);
+        assert_eq!(keys(sk.range(15..)), vec![15, 22, 32, 52]);
+        assert_eq!(keys(sk.range(..33)), vec![10, 12, 15, 22, 32]);
+        assert_eq!(keys(sk.range(..)), vec![10, 12, 15, 22, 32, 52]);
+        assert_eq!(keys(sk.range(13..=14)), Vec::<i32>::new());
+        assert_eq!(keys(sk.range(..=9)), Vec::<i32>::new());
+        assert_eq!(keys(sk.range(53..)), Vec::<i32>::new());
+        assert_eq!(
+            keys(sk.range((Bound::Excluded(12), Bound::Excluded(32)))),
+            vec![15, 22]
+        );
+
+        for (_, v) in sk.range_mut(15..32) {
+            *v = 1;
+        }
+        let values: Vec<_> = sk.range(..).map(|(_, v)| *v).collect();
+        assert_eq!(values, vec![10, 12, 1, 1, 32, 52]);
+    }
+
+    #[test]
+    #[should_panic(expected = "range start is greater than range end")]
+    fn range_reversed() {
+        let mut sk = SkipList::new();
+        sk.insert(1, 1);
+        sk.range((Bound::Included(3), Bound::Excluded(2)));
+    }
+
+    #[test]
+    fn first_last() {
+        let mut sk = SkipList::new();
+        assert!(sk.is_empty());
+        assert_eq!(sk.first_key_value(), None);
+        assert_eq!(sk.last_key_value(), None);
+        assert_eq!(sk.pop_first(), None);
+        assert_eq!(sk.pop_last(), None);
+
+        for i in 0..100 {
+            sk.insert(i, i * 10);
+        }
+        assert_eq!(sk.len(), 100);
+        assert!(sk.contains_key(&42));
+        assert!(!sk.contains_key(&100));
+        assert_eq!(sk.first_key_value(), Some((&0, &0)));
+        assert_eq!(sk.last_key_value(), Some((&99, &990)));
+        assert_eq!(sk.pop_first(), Some((0, 0)));
+        assert_eq!(sk.pop_last(), Some((99, 990)));
+        assert_eq!(sk.len(), 98);
+        assert_eq!(sk.first_key_value(), Some((&1, &10)));
+        assert_eq!(sk.last_key_value(), Some((&98, &980)));
+
+        sk.clear();
+        assert!(sk.is_empty());
+        assert_eq!(sk.get(&1), None);
+        sk.insert(7, 7);
+        assert_eq!(sk.iter().collect::<Vec<_>>(), vec![(&7, &7)]);
+    }
+
+    #[test]
+    fn double_ended() {
+        let mut sk = SkipList::new();
+        for i in (0..50).rev() {
+            sk.insert(i, i);
+        }
+
+        assert!(sk.iter().rev().map(|(k, _)| *k).eq((0..50).rev()));
+        assert_eq!(sk.iter().len(), 50);
+        assert!(sk.range(10..20).rev().map(|(k, _)| *k).eq((10..20).rev()));
+        assert_eq!(sk.range(..=0).next_back(), Some((&0, &0)));
+        assert_eq!(sk.range(50..).next_back(), None);
+
+        // Latest 3 entries before 30.
+        let latest: Vec<_> = sk.range(..30).rev().take(3).map(|(k, _)| *k).collect();
+        assert_eq!(latest, vec![29, 28, 27]);
+
+        let mut it = sk.range(5..=8);
+        assert_eq!(it.next(), Some((&5, &5)));
+        assert_eq!(it.next_back(), Some((&8, &8)));
+        assert_eq!(it.next_back(), Some((&7, &7)));
+        assert_eq!(it.next(), Some((&6, &6)));
+        assert_eq!(it.next(), None);
+        assert_eq!(it.next_back(), None);
+
+        for (k, v) in sk.range_mut(40..).rev() {
+            *v = k * 2;
         }
+        let mut it = sk.iter_mut();
+        assert_eq!(it.len(), 50);
+        assert_eq!(it.next_back(), Some((&49, &mut 98)));
+        assert_eq!(it.next(), Some((&0, &mut 0)));
+        assert_eq!(it.len(), 48);
+
+        let mut it = sk.into_iter();
+        assert_eq!(it.next_back(), Some((49, 98)));
+        assert_eq!(it.next(), Some((0, 0)));
+        assert_eq!(it.len(), 48);
+        let rest: Vec<_> = it.rev().map(|(k, _)| k).collect();
+        assert_eq!(rest, (1..49).rev().collect::<Vec<_>>());
+    }
 
-        println!("start range");
-        for (k, v) in sk.range(15..31) {
-            println!("range_kv {}: {}", k, v)
+    #[test]
+    fn drop_long_list() {
+        const N: usize = 3_000_000;
+
+        let sk = SkipList::from_sorted_iter((0..N).map(|i| (i, i))).unwrap();
+        assert_eq!(sk.len(), N);
+        drop(sk);
+
+        let mut sk = SkipList::from_sorted_iter((0..N).map(|i| (i, i))).unwrap();
+        sk.clear();
+        assert!(sk.is_empty());
+        sk.insert(1, 1);
+        assert_eq!(sk.len(), 1);
+
+        let sk = SkipList::from_sorted_iter((0..N).map(|i| (i, i))).unwrap();
+        let mut iter = sk.into_iter();
+        assert_eq!(iter.next(), Some((0, 0)));
+        assert_eq!(iter.next_back(), Some((N - 1, N - 1)));
+        drop(iter);
+    }
+
+    #[test]
+    fn level_generator() {
+        let mut a = SkipList::with_level_generator(GeometricalLevelGenerator::seeded(3));
+        let mut b = SkipList::with_level_generator(GeometricalLevelGenerator::seeded(3));
+        for i in 0..1000 {
+            a.insert(i, i);
+            b.insert(i, i);
         }
+        assert_eq!(heights(&a), heights(&b));
 
-        println!("start range2: 15..32");
-        for (k, v) in sk.range(15..32) {
-            println!("range_kv {}: {}", k, v)
+        let g = GeometricalLevelGenerator::new(3, 0.75, StdRng::seed_from_u64(3));
+        let mut sk = SkipList::with_level_generator(g);
+        for i in 0..1000 {
+            sk.insert(i, i);
         }
+        assert!(heights(&sk).iter().all(|h| *h <= 3));
+        assert_eq!(sk.nexts.len(), 3);
+        assert!(sk.iter().map(|(k, _)| *k).eq(0..1000));
+    }
 
-        println!("start range2: 15..");
-        for (k, v) in sk.range(15..) {
-            println!("range_kv {}: {}", k, v)
+    #[test]
+    fn same_as_btreemap() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut sk = SkipList::with_level_generator(GeometricalLevelGenerator::seeded(5));
+        let mut bt = BTreeMap::new();
+        for _ in 0..5000 {
+            let k = rng.gen_range(0, 500);
+            match rng.gen_range(0, 6) {
+                0..=2 => assert_eq!(sk.insert(k, k), bt.insert(k, k)),
+                3 => assert_eq!(sk.remove(&k), bt.remove(&k)),
+                4 => assert_eq!(sk.pop_first(), bt.pop_first()),
+                _ => assert_eq!(sk.pop_last(), bt.pop_last()),
+            }
+            assert_eq!(sk.len(), bt.len());
+        }
+        assert!(sk.iter().eq(bt.iter()));
+        for _ in 0..200 {
+            let a = rng.gen_range(0, 500);
+            let b = rng.gen_range(a, 501);
+            assert!(sk.range(a..b).eq(bt.range(a..b)));
+            assert!(sk.range(a..=b).eq(bt.range(a..=b)));
+            assert!(sk.range(..b).eq(bt.range(..b)));
+            assert!(sk.range(a..).eq(bt.range(a..)));
+            assert!(sk.range(a..=b).rev().eq(bt.range(a..=b).rev()));
         }
+    }
 
-        println!("start range2: ..33");
-        for (k, v) in sk.range(..33) {
-            println!("range_kv {}: {}", k, v)
+    #[test]
+    fn rank_and_index() {
+        let mut sk = SkipList::new();
+        for i in (0..100).rev() {
+            sk.insert(i * 2, i);
         }
+        assert_eq!(sk.get_by_index(0), Some((&0, &0)));
+        assert_eq!(sk.get_by_index(99), Some((&198, &99)));
+        assert_eq!(sk.get_by_index(100), None);
+        assert_eq!(sk.rank(&0), Some(0));
+        assert_eq!(sk.rank(&50), Some(25));
+        assert_eq!(sk.rank(&51), None);
+        assert_eq!(sk.rank(&200), None);
+        assert_eq!(sk.range_count(..), 100);
+        assert_eq!(sk.range_count(10..20), 5);
+        assert_eq!(sk.range_count(10..=20), 6);
+        assert_eq!(sk.range_count((Bound::Excluded(10), Bound::Included(20))), 5);
+        assert_eq!(sk.range_count(11..12), 0);
+        assert_eq!(sk.range_count(300..), 0);
+        assert_eq!(sk.range(10..).nth(3), Some((&16, &8)));
+        assert_eq!(sk.range(10..20).nth(5), None);
+        assert_eq!(sk.iter().nth(99), Some((&198, &99)));
+
+        let mut range = sk.range_mut(20..=40);
+        assert_eq!(range.len(), 11);
+        assert_eq!(range.nth(2), Some((&24, &mut 12)));
+        assert_eq!(range.next_back(), Some((&40, &mut 20)));
+        assert_eq!(range.len(), 7);
+
+        let empty: SkipList<i32, i32> = SkipList::new();
+        assert_eq!(empty.get_by_index(0), None);
+        assert_eq!(empty.rank(&0), None);
+        assert_eq!(empty.range_count(..), 0);
+    }
 
-        println!("start range2: ..");
-        for (k, v) in sk.range(..) {
-            println!("range_kv {}: {}", k, v)
+    #[test]
+    fn rank_same_as_btreemap() {
+        let mut rng = StdRng::seed_from_u64(6);
+        let mut sk = SkipList::with_level_generator(GeometricalLevelGenerator::seeded(6));
+        let mut bt = BTreeMap::new();
+        for round in 0..3000 {
+            let k = rng.gen_range(0, 300);
+            match rng.gen_range(0, 5) {
+                0..=2 => assert_eq!(sk.insert(k, k), bt.insert(k, k)),
+                3 => assert_eq!(sk.remove(&k), bt.remove(&k)),
+                _ => assert_eq!(sk.pop_last(), bt.pop_last()),
+            }
+            if round % 100 == 0 {
+                check_widths(&sk);
+            }
         }
+        check_widths(&sk);
 
-        println!("start range_mut: 15..32");
-        for (k, v) in sk.range_mut(15..32) {
-            println!("range_mut_kv {}: {}", k, v);
-            *v = 1;
+        for i in 0..=bt.len() {
+            assert_eq!(sk.get_by_index(i), bt.iter().nth(i));
+            assert_eq!(sk.iter().nth(i), bt.iter().nth(i));
         }
+        for k in 0..300 {
+            assert_eq!(sk.rank(&k), bt.keys().position(|x| *x == k));
+        }
+        for _ in 0..200 {
+            let a = rng.gen_range(0, 300);
+            let b = rng.gen_range(a, 301);
+            let n = rng.gen_range(0, 50);
+            assert_eq!(sk.range_count(a..b), bt.range(a..b).count());
+            assert_eq!(sk.range_count(a..=b), bt.range(a..=b).count());
+            assert_eq!(sk.range(a..=b).nth(n), bt.range(a..=b).nth(n));
+        }
+    }
+
+    #[test]
+    fn split_off_forks_generator() {
+        let tail = || {
+            let mut sk = SkipList::with_level_generator(GeometricalLevelGenerator::seeded(9));
+            for i in 0..100 {
+                sk.insert(i, i);
+            }
+            let mut tail = sk.split_off(&50);
+            for i in 100..200 {
+                tail.insert(i, i);
+            }
+            tail
+        };
+        assert_eq!(heights(&tail()), heights(&tail()));
+    }
+
+    #[test]
+    fn split_off_append() {
+        let mut sk = SkipList::new();
+        for i in 0..100 {
+            sk.insert(i, i);
+        }
+        let mut other = sk.split_off(&40);
+        check_widths(&sk);
+        check_widths(&other);
+        assert!(sk.iter().map(|(k, _)| *k).eq(0..40));
+        assert!(other.iter().rev().map(|(k, _)| *k).eq((40..100).rev()));
+        assert_eq!(other.get_by_index(0), Some((&40, &40)));
+        assert!(sk.split_off(&100).is_empty());
+        assert_eq!(sk.len(), 40);
+
+        sk.append(&mut other);
+        check_widths(&sk);
+        assert!(other.is_empty());
+        assert!(sk.iter().map(|(k, _)| *k).eq(0..100));
+        assert!(sk.iter().rev().map(|(k, _)| *k).eq((0..100).rev()));
+        assert_eq!(sk.rank(&70), Some(70));
+
+        let mut all = sk.split_off(&0);
+        assert!(sk.is_empty());
+        sk.append(&mut all);
+        assert_eq!(sk.len(), 100);
+        sk.insert(100, 100);
+        assert_eq!(sk.last_key_value(), Some((&100, &100)));
+
+        let mut odd = SkipList::new();
+        for i in (1..200).step_by(2) {
+            odd.insert(i, -i);
+        }
+        sk.append(&mut odd);
+        check_widths(&sk);
+        assert!(odd.is_empty());
+        assert_eq!(sk.len(), 151);
+        assert_eq!(sk.get(&3), Some(&-3));
+        assert_eq!(sk.get(&4), Some(&4));
+        assert_eq!(sk.get(&199), Some(&-199));
+        let keys: Vec<_> = sk.iter().map(|(k, _)| *k).collect();
+        assert!(sk.iter().rev().map(|(k, _)| *k).eq(keys.into_iter().rev()));
+    }
+
+    #[test]
+    fn split_append_same_as_btreemap() {
+        let mut rng = StdRng::seed_from_u64(9);
+        for _ in 0..30 {
+            let mut sk = SkipList::new();
+            let mut bt = BTreeMap::new();
+            for _ in 0..rng.gen_range(0, 300) {
+                let k = rng.gen_range(0, 300);
+                sk.insert(k, k);
+                bt.insert(k, k);
+            }
+            let at = rng.gen_range(0, 300);
+            let mut sk_right = sk.split_off(&at);
+            let mut bt_right = bt.split_off(&at);
+            check_widths(&sk);
+            check_widths(&sk_right);
+            assert!(sk.iter().eq(bt.iter()));
+            assert!(sk_right.iter().eq(bt_right.iter()));
+
+            for _ in 0..rng.gen_range(0, 50) {
+                let k = rng.gen_range(0, 300);
+                sk_right.insert(k, k + 1);
+                bt_right.insert(k, k + 1);
+            }
+            sk.append(&mut sk_right);
+            bt.append(&mut bt_right);
+            check_widths(&sk);
+            assert!(sk_right.is_empty());
+            assert!(sk.iter().eq(bt.iter()));
+            assert!(sk.iter().rev().eq(bt.iter().rev()));
+        }
+    }
+
+    #[test]
+    fn from_sorted_iter() {
+        let sk = SkipList::from_sorted_iter((0..1000).map(|i| (i, i))).unwrap();
+        check_widths(&sk);
+        assert_eq!(sk.len(), 1000);
+        assert!(sk.iter().map(|(k, _)| *k).eq(0..1000));
+        assert!(sk.iter().rev().map(|(k, _)| *k).eq((0..1000).rev()));
+        assert_eq!(sk.rank(&500), Some(500));
+
+        let sk = SkipList::from_sorted_iter(vec![(1, 'a'), (2, 'b'), (2, 'c'), (3, 'd')]).unwrap();
+        assert!(sk.iter().eq(vec![(&1, &'a'), (&2, &'c'), (&3, &'d')]));
+
+        let err = SkipList::from_sorted_iter(vec![(1, ()), (3, ()), (2, ())]).err();
+        assert_eq!(err, Some(SkipListError::UnsortedKey(2)));
+        let empty: SkipList<i32, ()> = SkipList::from_sorted_iter(vec![]).unwrap();
+        assert!(empty.is_empty());
+
+        let reverse = ReverseComparator(OrdComparator);
+        let entries = (0..100).rev().map(|i| (i, i));
+        let sk = SkipList::from_sorted_iter_with_comparator(entries, reverse).unwrap();
+        check_widths(&sk);
+        assert!(sk.iter().map(|(k, _)| *k).eq((0..100).rev()));
+        let err = SkipList::from_sorted_iter_with_comparator(vec![(1, ()), (2, ())], reverse).err();
+        assert_eq!(err, Some(SkipListError::UnsortedKey(1)));
+    }
+
+    #[test]
+    fn from_iter_extend() {
+        let mut rng = StdRng::seed_from_u64(10);
+        let entries: Vec<(i32, i32)> = (0..2000)
+            .map(|i| (rng.gen_range(0, 500), i))
+            .collect();
+        let sk: SkipList<_, _> = entries.iter().cloned().collect();
+        let bt: BTreeMap<_, _> = entries.iter().cloned().collect();
+        check_widths(&sk);
+        assert!(sk.iter().eq(bt.iter()));
+
+        let mut sk = SkipList::new();
+        let mut bt = BTreeMap::new();
+        sk.extend(entries.iter().cloned());
+        bt.extend(entries.iter().cloned());
+        assert!(sk.iter().eq(bt.iter()));
+        sk.extend((500..600).map(|i| (i, i)));
+        bt.extend((500..600).map(|i| (i, i)));
+        check_widths(&sk);
+        assert!(sk.iter().eq(bt.iter()));
+    }
+
+    // An out of order key doesn't lose the finger for the keys after it.
+    #[test]
+    fn mostly_sorted_extend() {
+        let mut sk = SkipList::with_level_generator(GeometricalLevelGenerator::seeded(3));
+        for i in 0..1000 {
+            let k = if i % 50 == 25 { i - 20 } else { i * 2 };
+            sk.extend(Some((k, i)));
+            let (tails, ranks) = sk.finger.clone().unwrap();
+            assert_eq!((tails, ranks), sk._get_tail_nodes());
+        }
+        check_widths(&sk);
+        let mut expected: Vec<_> = (0..1000)
+            .map(|i| if i % 50 == 25 { i - 20 } else { i * 2 })
+            .collect();
+        expected.sort_unstable();
+        assert!(sk.iter().map(|(k, _)| *k).eq(expected));
+    }
 
-        println!("start range3: ..");
-        for (k, v) in sk.range(..) {
-            println!("range_kv {}: {}", k, v)
+    #[test]
+    fn increasing_insert() {
+        let mut sk = SkipList::new();
+        for i in 0..1000 {
+            assert_eq!(sk.insert(i, i), None);
+            if i % 100 == 50 {
+                // Drop the finger and start again from a searched position.
+                assert_eq!(sk.remove(&(i - 10)), Some(i - 10));
+                assert_eq!(sk.insert(i - 10, 0), None);
+                assert_eq!(sk.insert(i, 0), Some(i));
+            }
+            if i % 100 == 70 {
+                assert_eq!(sk.pop_last(), Some((i, i)));
+                sk.entry(i).or_insert(i);
+            }
         }
+        check_widths(&sk);
+        assert!(sk.iter().map(|(k, _)| *k).eq(0..1000));
+        let mut other = sk.split_off(&500);
+        sk.insert(500, 500);
+        sk.insert(501, 501);
+        other.insert(1000, 1000);
+        check_widths(&sk);
+        check_widths(&other);
+        assert!(sk.iter().map(|(k, _)| *k).eq(0..502));
+        assert!(other.iter().map(|(k, _)| *k).eq(500..1001));
+    }
 
+    #[test]
+    fn comparator() {
+        let mut rng = StdRng::seed_from_u64(12);
+        let mut sk = SkipList::with_comparator(ReverseComparator(OrdComparator));
+        let mut bt = BTreeMap::new();
+        for _ in 0..2000 {
+            let k: i32 = rng.gen_range(0, 500);
+            if rng.gen_range(0, 3) == 0 {
+                assert_eq!(sk.remove(&k), bt.remove(&k));
+            } else {
+                assert_eq!(sk.insert(k, k), bt.insert(k, k));
+            }
+        }
+        check_widths(&sk);
+        assert!(sk.iter().eq(bt.iter().rev()));
+        let range = (Bound::Included(400), Bound::Included(100));
+        assert!(sk.range(range).eq(bt.range(100..=400).rev()));
+        assert_eq!(sk.range_count(..250), bt.range(251..).count());
+        assert_eq!(sk.get(&bt.keys().next().copied().unwrap()), bt.values().next());
+
+        let mut other = sk.split_off(&250);
+        assert!(other.iter().all(|(k, _)| *k <= 250));
+        other.insert(1000, 1000);
+        sk.append(&mut other);
+        check_widths(&sk);
+        bt.insert(1000, 1000);
+        assert!(sk.into_iter().eq(bt.into_iter().rev()));
+
+        let mut sk = SkipList::with_comparator(|a: &&str, b: &&str| {
+            a.to_lowercase().cmp(&b.to_lowercase())
+        });
+        sk.insert("b", 1);
+        sk.insert("A", 2);
+        assert_eq!(sk.insert("B", 3), Some(1));
+        *sk.entry("a").or_insert(0) += 10;
+        assert_eq!(sk.iter().collect::<Vec<_>>(), vec![(&"A", &12), (&"b", &3)]);
+        assert_eq!(sk.rank(&"B"), Some(1));
     }
 
-    // struct Node {
-    //     a: i32,
-    //     b: i32,
-    // }
+    #[test]
+    fn remove_range() {
+        let mut rng = StdRng::seed_from_u64(15);
+        let mut sk: SkipList<i32, i32> = (0..1000).map(|i| (i, i)).collect();
+        let mut bt: BTreeMap<i32, i32> = (0..1000).map(|i| (i, i)).collect();
+        for _ in 0..100 {
+            let s = rng.gen_range(-10, 1010);
+            let e = rng.gen_range(s, 1010);
+            let removed: Vec<_> = bt.range(s..e).map(|(k, _)| *k).collect();
+            for k in &removed {
+                bt.remove(k);
+            }
+            if rng.gen() {
+                assert_eq!(sk.remove_range(s..e), removed.len());
+            } else {
+                assert!(sk.drain_range(s..e).map(|(k, _)| k).eq(removed));
+            }
+            check_widths(&sk);
+            assert!(sk.iter().eq(bt.iter()));
+            let k = rng.gen_range(0, 1000);
+            sk.insert(k, k);
+            bt.insert(k, k);
+        }
 
-    // #[test]
-    // fn multi_mut() {
-    //     let mut n = Node{a: 0, b: 0};
-    //     let pn1: *mut _ = &mut n;
-    //     let pn2: *mut _ = &mut n;
+        let mut drain = sk.drain_range((Bound::Excluded(100), Bound::Included(900)));
+        let first = drain.next();
+        assert_eq!(first, bt.range(101..=900).map(|(k, v)| (*k, *v)).next());
+        assert_eq!(drain.next_back(), bt.range(101..=900).map(|(k, v)| (*k, *v)).next_back());
+        drop(drain);
+        assert_eq!(sk.range(101..=900).count(), 0);
+        let len = sk.len();
+        assert_eq!(sk.remove_range(..), len);
+        assert!(sk.is_empty());
+        assert_eq!(sk.remove_range(..), 0);
+        sk.insert(1, 1);
+        check_widths(&sk);
+    }
 
-    //     let upn1 = unsafe {
-    //         &mut *pn1
-    //     };
-    //     let upn2 = unsafe {
-    //         &mut *pn2
-    //     };
+    #[test]
+    fn retain_extract_if() {
+        let mut sk: SkipList<i32, i32> = (0..1000).map(|i| (i, i)).collect();
+        sk.retain(|k, v| {
+            *v += 1;
+            k % 3 != 0
+        });
+        check_widths(&sk);
+        let expected = (0..1000).filter(|k| k % 3 != 0).map(|k| (k, k + 1));
+        assert!(sk.iter().map(|(k, v)| (*k, *v)).eq(expected));
+
+        let evens: Vec<_> = sk.extract_if(|k, _| k % 2 == 0).map(|(k, _)| k).collect();
+        assert!(evens.into_iter().eq((0..1000).filter(|k| k % 6 == 2 || k % 6 == 4)));
+        check_widths(&sk);
+        assert!(sk.iter().map(|(k, _)| *k).eq((0..1000).filter(|k| k % 6 == 1 || k % 6 == 5)));
+
+        // Entries the iterator doesn't reach are kept.
+        let mut extract = sk.extract_if(|_, _| true);
+        assert_eq!(extract.next(), Some((1, 2)));
+        drop(extract);
+        assert_eq!(sk.len(), 332);
+        sk.retain(|_, _| false);
+        assert!(sk.is_empty());
+        check_widths(&sk);
+    }
 
-    //     upn1.a = 1;
-    //     upn2.b = 2;
-    //     upn1.a = 11;
+    #[test]
+    fn floor_ceiling() {
+        let mut rng = StdRng::seed_from_u64(20);
+        let mut sk = SkipList::new();
+        let mut map = BTreeMap::new();
+        for _ in 0..500 {
+            let k = rng.gen_range(0, 2000) * 2;
+            sk.insert(k, k);
+            map.insert(k, k);
+        }
+        for q in -2..4003 {
+            assert_eq!(sk.floor(&q), map.range(..=q).next_back());
+            assert_eq!(sk.ceiling(&q), map.range(q..).next());
+            assert_eq!(sk.lower(&q), map.range(..q).next_back());
+            assert_eq!(sk.higher(&q), map.range(q + 1..).next());
+        }
 
-    //     println!("{}", upn1.a);
-    //     println!("{}", upn2.a);
-    //     println!("{}", upn2.b);
-    // }
+        let (first, last) = (*map.keys().next().unwrap(), *map.keys().next_back().unwrap());
+        *sk.floor_mut(&(first + 1)).unwrap().1 += 1;
+        *sk.ceiling_mut(&(last - 1)).unwrap().1 += 1;
+        *sk.lower_mut(&(last + 1)).unwrap().1 += 1;
+        *sk.higher_mut(&(first - 1)).unwrap().1 += 1;
+        assert_eq!(sk.get(&first), Some(&(first + 2)));
+        assert_eq!(sk.get(&last), Some(&(last + 2)));
+        assert_eq!(sk.lower_mut(&first), None);
+        assert_eq!(sk.higher_mut(&last), None);
+
+        let sk: SkipList<i32, i32, _> = SkipList::with_comparator(ReverseComparator(OrdComparator));
+        assert_eq!(sk.floor(&1), None);
+        let mut sk = SkipList::with_comparator(ReverseComparator(OrdComparator));
+        sk.insert(1, ());
+        sk.insert(3, ());
+        assert_eq!(sk.floor(&2), Some((&3, &())));
+        assert_eq!(sk.higher(&2), Some((&1, &())));
+    }
 
-    // #[test]
-    // fn multi_mut2() {
-    //     let mut n = Node{a: 0, b: 0};
-    //     let rn2 = &mut n;
-    //     let rn1 = &mut n;
+    #[test]
+    fn send_sync() {
+        fn send_sync<T: Send + Sync>() {}
+        send_sync::<SkipList<String, Vec<u8>>>();
+        send_sync::<Iter<'_, String, u8>>();
+        send_sync::<IterMut<'_, String, u8>>();
+        send_sync::<Range<'_, String, u8>>();
+        send_sync::<RangeMut<'_, String, u8>>();
+        send_sync::<IntoIter<String, u8>>();
+        send_sync::<CursorMut<'_, String, u8>>();
+        send_sync::<Entry<'_, String, u8>>();
+
+        let sk: SkipList<i32, String> = (0..1000).map(|i| (i, i.to_string())).collect();
+        let sk = std::thread::spawn(move || {
+            let mut sk = sk;
+            sk.retain(|k, _| k % 2 == 0);
+            sk
+        })
+        .join()
+        .unwrap();
+        assert_eq!(sk.len(), 500);
+        let sk = &sk;
+        std::thread::scope(|s| {
+            let evens = s.spawn(|| sk.iter().filter(|(k, _)| *k % 4 == 0).count());
+            let odds = s.spawn(|| sk.range(..500).count());
+            assert_eq!((evens.join().unwrap(), odds.join().unwrap()), (250, 250));
+        });
+        let iter = (0..100).map(|i| (i, i)).collect::<SkipList<_, _>>().into_iter();
+        let rest = std::thread::spawn(move || iter.skip(90).collect::<Vec<_>>()).join().unwrap();
+        assert_eq!(rest.len(), 10);
+    }
 
-    //     println!("{}", rn1.a)
+    #[test]
+    fn std_traits() {
+        let mut sk: SkipList<i32, String> = (0..100).map(|i| (i, i.to_string())).collect();
+        let mut copy = sk.clone();
+        check_widths(&copy);
+        let seeded: SkipList<i32, ()> =
+            SkipList::with_level_generator(GeometricalLevelGenerator::seeded(25));
+        let (mut a, mut b) = (seeded.clone(), seeded.clone());
+        a.extend((0..100).map(|i| (i, ())));
+        b.extend((0..100).map(|i| (i, ())));
+        assert_eq!(heights(&a), heights(&b));
+        assert_eq!(copy, sk);
+        copy.insert(100, "100".to_string());
+        assert_ne!(copy, sk);
+        assert!(sk < copy);
+        copy.remove(&100);
+        copy.insert(50, "fifty".to_string());
+        assert_eq!(copy.cmp(&sk), Ordering::Greater);
+        assert_eq!(sk[&42], "42");
+
+        let hash = |sk: &SkipList<i32, String>| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            sk.hash(&mut hasher);
+            hasher.finish()
+        };
+        copy.insert(50, "50".to_string());
+        assert_eq!(hash(&copy), hash(&sk));
 
-    // }
+        for (_, v) in &mut sk {
+            v.push('!');
+        }
+        assert_eq!((&sk).into_iter().next(), Some((&0, &"0!".to_string())));
+        let small: SkipList<_, _> = vec![(1, 'a'), (2, 'b')].into_iter().collect();
+        assert_eq!(format!("{:?}", small), "{1: 'a', 2: 'b'}");
+        assert_eq!(SkipList::<i32, i32>::default(), SkipList::new());
+    }
 
+    #[test]
+    #[should_panic(expected = "no entry found for key")]
+    fn index_missing() {
+        let sk: SkipList<i32, i32> = SkipList::new();
+        let _ = sk[&1];
+    }
 }
@@ -0,0 +1,361 @@
+use super::{Node, PreNodes, SkipList};
+use crate::comparator::{Comparator, OrdComparator};
+use crate::error::SkipListError;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::fmt::{self, Debug};
+
+/// A cursor over a `SkipList` which can edit the list in place.
+///
+/// The cursor points at an entry, or past the end once it walks off the
+/// last entry. It keeps the pre nodes of the current entry on every level,
+/// so moving to the next entry, and inserting or removing around the
+/// cursor, don't search the list again.
+///
+/// This `struct` is constructed from the [`lower_bound_cursor`] and
+/// [`upper_bound_cursor`] methods on [`SkipList`].
+///
+/// [`lower_bound_cursor`]: SkipList::lower_bound_cursor
+/// [`upper_bound_cursor`]: SkipList::upper_bound_cursor
+pub struct CursorMut<'a, K, V, C = OrdComparator> {
+    list: &'a mut SkipList<K, V, C>,
+    // Pre nodes of the current entry on every level and their positions.
+    pres: Vec<*mut Node<K, V>>,
+    ranks: Vec<usize>,
+}
+
+// The pre nodes point into the list the cursor borrows mutably.
+unsafe impl<'a, K: Send, V: Send, C: Send> Send for CursorMut<'a, K, V, C> {}
+
+unsafe impl<'a, K: Sync, V: Sync, C: Sync> Sync for CursorMut<'a, K, V, C> {}
+
+impl<K, V, C: Comparator<K>> SkipList<K, V, C> {
+    /// Returns a cursor pointing at the first entry whose key is not less
+    /// than `q`, or past the end if there is none.
+    pub fn lower_bound_cursor<Q>(&mut self, q: &Q) -> CursorMut<'_, K, V, C>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let (pres, ranks) = self._get_pre_nodes(q, false);
+        CursorMut { list: self, pres, ranks }
+    }
+
+    /// Returns a cursor pointing at the first entry whose key is greater
+    /// than `q`, or past the end if there is none.
+    pub fn upper_bound_cursor<Q>(&mut self, q: &Q) -> CursorMut<'_, K, V, C>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let (pres, ranks) = self._get_pre_nodes(q, true);
+        CursorMut { list: self, pres, ranks }
+    }
+}
+
+impl<K, V, C> SkipList<K, V, C> {
+    // A cursor pointing at the first entry.
+    pub(super) fn _cursor_front(&mut self) -> CursorMut<'_, K, V, C> {
+        let height = self.nexts.len();
+        CursorMut {
+            list: self,
+            pres: vec![core::ptr::null_mut(); height],
+            ranks: vec![0; height],
+        }
+    }
+}
+
+impl<'a, K, V, C> CursorMut<'a, K, V, C> {
+    /// Returns the key of the current entry, `None` past the end.
+    pub fn key(&self) -> Option<&K> {
+        let current = self._current();
+        if current.is_null() {
+            return None;
+        }
+        Some(unsafe { &(*current).key })
+    }
+
+    /// Returns the value of the current entry, `None` past the end.
+    pub fn value(&self) -> Option<&V> {
+        let current = self._current();
+        if current.is_null() {
+            return None;
+        }
+        Some(unsafe { &(*current).value })
+    }
+
+    /// Returns a mutable reference to the value of the current entry,
+    /// `None` past the end.
+    pub fn value_mut(&mut self) -> Option<&mut V> {
+        let current = self._current();
+        if current.is_null() {
+            return None;
+        }
+        Some(unsafe { &mut (*current).value })
+    }
+
+    /// Returns the position of the current entry in the sorted order,
+    /// starting from 0, or `None` past the end.
+    pub fn index(&self) -> Option<usize> {
+        if self._current().is_null() {
+            return None;
+        }
+        Some(self.ranks[0])
+    }
+
+    /// Moves to the next entry, or past the end from the last entry. Does
+    /// nothing past the end.
+    pub fn move_next(&mut self) {
+        let current = self._current();
+        if !current.is_null() {
+            self._step_over(current);
+        }
+    }
+
+    /// Returns the entry after the current one without moving the cursor.
+    pub fn peek_next(&self) -> Option<(&K, &V)> {
+        let current = self._current();
+        if current.is_null() {
+            return None;
+        }
+        let next = unsafe { (&(*current).nexts)[0] };
+        if next.is_null() {
+            return None;
+        }
+        let node = unsafe { &*next };
+        Some((&node.key, &node.value))
+    }
+
+    /// Removes the current entry and returns it, the cursor moves to the
+    /// next entry. Returns `None` past the end.
+    pub fn remove_current(&mut self) -> Option<(K, V)> {
+        let current = self._current();
+        if current.is_null() {
+            return None;
+        }
+        // The pre nodes of the removed node are the pre nodes of the next.
+        let result = self.list._unlink(&self.pres, current);
+        let height = self.list.nexts.len();
+        self.pres.truncate(height);
+        self.ranks.truncate(height);
+        Some(result)
+    }
+
+    /// Removes the entry after the current one and returns it, the cursor
+    /// doesn't move. Returns `None` if there is no next entry.
+    pub fn remove_next(&mut self) -> Option<(K, V)> {
+        let current = self._current();
+        if current.is_null() {
+            return None;
+        }
+        let next = unsafe { (&(*current).nexts)[0] };
+        if next.is_null() {
+            return None;
+        }
+        let (pres, _) = self._pres_after(current);
+        let result = self.list._unlink(&pres, next);
+        let height = self.list.nexts.len();
+        self.pres.truncate(height);
+        self.ranks.truncate(height);
+        Some(result)
+    }
+
+    // The key and the mutable value of the current entry.
+    pub(super) fn _current_mut(&mut self) -> Option<(&K, &mut V)> {
+        let current = self._current();
+        if current.is_null() {
+            return None;
+        }
+        let node = unsafe { &mut *current };
+        Some((&node.key, &mut node.value))
+    }
+
+    fn _current(&self) -> *mut Node<K, V> {
+        self.list._next_of(&self.pres)
+    }
+
+    // Make `node`, which must be the current entry, a pre node.
+    fn _step_over(&mut self, node: *mut Node<K, V>) {
+        let pos = self.ranks[0] + 1;
+        let height = unsafe { (*node).nexts.len() };
+        for l in 0..height {
+            self.pres[l] = node;
+            self.ranks[l] = pos;
+        }
+    }
+
+    // The pre nodes of the entry after `node`, which must be the current
+    // entry.
+    fn _pres_after(&self, node: *mut Node<K, V>) -> PreNodes<K, V> {
+        let mut pres = self.pres.clone();
+        let mut ranks = self.ranks.clone();
+        let height = unsafe { (*node).nexts.len() };
+        for l in 0..height {
+            pres[l] = node;
+            ranks[l] = self.ranks[0] + 1;
+        }
+        (pres, ranks)
+    }
+}
+
+impl<'a, K, V, C: Comparator<K>> CursorMut<'a, K, V, C> {
+    /// Inserts an entry right after the current one, or at the back of the
+    /// list past the end. The cursor doesn't move.
+    ///
+    /// Returns `SkipListError::KeyOutOfOrder` if `k` is not greater than
+    /// the current key or not less than the next key.
+    pub fn insert_after(&mut self, k: K, v: V) -> Result<(), SkipListError> {
+        let current = self._current();
+        let (pre, next) = if current.is_null() {
+            (self.pres.first().copied().unwrap_or(current), current)
+        } else {
+            (current, unsafe { (&(*current).nexts)[0] })
+        };
+        let comparator = &self.list.comparator;
+        let after_pre = pre.is_null()
+            || comparator.compare(unsafe { &(*pre).key }, &k) == Ordering::Less;
+        let before_next = next.is_null()
+            || comparator.compare(&k, unsafe { &(*next).key }) == Ordering::Less;
+        if !after_pre || !before_next {
+            return Err(SkipListError::KeyOutOfOrder);
+        }
+
+        let (mut pres, mut ranks) = if current.is_null() {
+            (self.pres.clone(), self.ranks.clone())
+        } else {
+            self._pres_after(current)
+        };
+        let node = self.list._link(&mut pres, &mut ranks, k, v);
+        if self.list.nexts.len() > self.pres.len() {
+            // The new top level only has the new node, after the cursor.
+            self.pres.push(core::ptr::null_mut());
+            self.ranks.push(0);
+        }
+        if current.is_null() {
+            // Stay past the end.
+            self._step_over(node);
+        }
+        Ok(())
+    }
+}
+
+impl<'a, K: Debug, V: Debug, C> Debug for CursorMut<'a, K, V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.key(), self.value()) {
+            (Some(k), Some(v)) => f.debug_tuple("CursorMut").field(k).field(v).finish(),
+            _ => f.write_str("CursorMut(end)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::check_widths;
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeMap;
+    use std::format;
+
+    #[test]
+    fn cursor() {
+        let mut sk: SkipList<i32, i32> = (0..10).map(|i| (i * 10, i)).collect();
+        let mut cursor = sk.lower_bound_cursor(&15);
+        assert_eq!(cursor.key(), Some(&20));
+        assert_eq!(cursor.index(), Some(2));
+        assert_eq!(cursor.peek_next(), Some((&30, &3)));
+        *cursor.value_mut().unwrap() += 100;
+        assert_eq!(cursor.insert_after(20, 0), Err(SkipListError::KeyOutOfOrder));
+        assert_eq!(cursor.insert_after(30, 0), Err(SkipListError::KeyOutOfOrder));
+        assert_eq!(cursor.insert_after(25, 0), Ok(()));
+        assert_eq!(cursor.key(), Some(&20));
+        assert_eq!(cursor.remove_next(), Some((25, 0)));
+        cursor.move_next();
+        assert_eq!(cursor.remove_current(), Some((30, 3)));
+        assert_eq!(cursor.insert_after(30, 3), Err(SkipListError::KeyOutOfOrder));
+        assert_eq!(cursor.key(), Some(&40));
+        assert_eq!(format!("{:?}", cursor), "CursorMut(40, 4)");
+
+        let mut cursor = sk.upper_bound_cursor(&90);
+        assert_eq!(cursor.key(), None);
+        assert_eq!(cursor.index(), None);
+        assert_eq!(cursor.insert_after(90, 0), Err(SkipListError::KeyOutOfOrder));
+        assert_eq!(cursor.insert_after(100, 10), Ok(()));
+        assert_eq!(cursor.insert_after(110, 11), Ok(()));
+        assert_eq!(cursor.key(), None);
+        cursor.move_next();
+        assert_eq!(cursor.remove_current(), None);
+        check_widths(&sk);
+        assert_eq!(sk.get(&20), Some(&102));
+        assert!(sk.iter().map(|(k, _)| *k).eq((0..12).filter(|i| *i != 3).map(|i| i * 10)));
+
+        let mut sk = SkipList::new();
+        let mut cursor = sk.lower_bound_cursor(&0);
+        for i in 0..100 {
+            cursor.insert_after(i, i).unwrap();
+        }
+        let mut cursor = sk.lower_bound_cursor(&0);
+        while cursor.remove_current().is_some() {}
+        assert!(sk.is_empty());
+        check_widths(&sk);
+    }
+
+    #[test]
+    fn coalesce_intervals() {
+        // Intervals keyed by start, merge the ones that touch or overlap.
+        let mut sk: SkipList<i32, i32> =
+            vec![(0, 2), (1, 4), (4, 5), (7, 8), (10, 12), (11, 11)].into_iter().collect();
+        let mut cursor = sk.lower_bound_cursor(&0);
+        while cursor.key().is_some() {
+            let end = *cursor.value().unwrap();
+            match cursor.peek_next() {
+                Some((start, _)) if *start <= end => {
+                    let (_, next_end) = cursor.remove_next().unwrap();
+                    *cursor.value_mut().unwrap() = end.max(next_end);
+                }
+                _ => cursor.move_next(),
+            }
+        }
+        assert_eq!(sk.into_iter().collect::<Vec<_>>(), vec![(0, 5), (7, 8), (10, 12)]);
+    }
+
+    #[test]
+    fn same_as_btreemap() {
+        let mut rng = StdRng::seed_from_u64(14);
+        let mut sk = SkipList::new();
+        let mut bt = BTreeMap::new();
+        for _ in 0..200 {
+            let k = rng.gen_range(0, 1000);
+            let mut cursor = sk.lower_bound_cursor(&k);
+            for _ in 0..rng.gen_range(0, 10) {
+                match rng.gen_range(0, 4) {
+                    0 => {
+                        if let Some((k, v)) = cursor.remove_current() {
+                            assert_eq!(bt.remove(&k), Some(v));
+                        }
+                    }
+                    1 => {
+                        if let Some((k, v)) = cursor.remove_next() {
+                            assert_eq!(bt.remove(&k), Some(v));
+                        }
+                    }
+                    2 => {
+                        let k = rng.gen_range(0, 1000);
+                        if cursor.insert_after(k, k).is_ok() {
+                            assert_eq!(bt.insert(k, k), None);
+                        }
+                    }
+                    _ => cursor.move_next(),
+                }
+                let index = cursor.index();
+                assert_eq!(index.and_then(|i| bt.iter().nth(i)), cursor.key().map(|k| (k, k)));
+            }
+            check_widths(&sk);
+            assert!(sk.iter().eq(bt.iter()));
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use super::{Node, SkipList};
+use crate::comparator::{Comparator, OrdComparator};
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt::{self, Debug};
+
+/// A view into a single entry in a `SkipList`, which may either be vacant
+/// or occupied.
+///
+/// This `enum` is constructed from the [`entry`] method on [`SkipList`].
+///
+/// [`entry`]: SkipList::entry
+pub enum Entry<'a, K, V, C = OrdComparator> {
+    Vacant(VacantEntry<'a, K, V, C>),
+    Occupied(OccupiedEntry<'a, K, V, C>),
+}
+
+/// A view into a vacant entry in a `SkipList`.
+pub struct VacantEntry<'a, K, V, C = OrdComparator> {
+    key: K,
+    list: &'a mut SkipList<K, V, C>,
+    // Pre nodes of `key` on every level and their positions, found while
+    // searching the entry.
+    pres: Vec<*mut Node<K, V>>,
+    ranks: Vec<usize>,
+}
+
+/// A view into an occupied entry in a `SkipList`.
+pub struct OccupiedEntry<'a, K, V, C = OrdComparator> {
+    list: &'a mut SkipList<K, V, C>,
+    pres: Vec<*mut Node<K, V>>,
+    node: *mut Node<K, V>,
+}
+
+// The pre nodes point into the list the entries borrow mutably.
+unsafe impl<'a, K: Send, V: Send, C: Send> Send for VacantEntry<'a, K, V, C> {}
+
+unsafe impl<'a, K: Sync, V: Sync, C: Sync> Sync for VacantEntry<'a, K, V, C> {}
+
+unsafe impl<'a, K: Send, V: Send, C: Send> Send for OccupiedEntry<'a, K, V, C> {}
+
+unsafe impl<'a, K: Sync, V: Sync, C: Sync> Sync for OccupiedEntry<'a, K, V, C> {}
+
+impl<K, V, C: Comparator<K>> SkipList<K, V, C> {
+    /// Gets the given key's corresponding entry in the list for in-place
+    /// manipulation.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, C> {
+        let (pres, ranks) = self._get_pre_nodes(&key, false);
+        let current = self._next_of(&pres);
+        let found = !current.is_null()
+            && self.comparator.compare(unsafe { &(*current).key }, &key) == Ordering::Equal;
+        if found {
+            Entry::Occupied(OccupiedEntry {
+                list: self,
+                pres,
+                node: current,
+            })
+        } else {
+            Entry::Vacant(VacantEntry {
+                key,
+                list: self,
+                pres,
+                ranks,
+            })
+        }
+    }
+}
+
+impl<'a, K, V, C> Entry<'a, K, V, C> {
+    /// Ensures a value is in the entry by inserting the default if empty,
+    /// and returns a mutable reference to the value in the entry.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting the result of the
+    /// default function if empty, and returns a mutable reference to the
+    /// value in the entry.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Same as `or_insert_with`, but the default function receives the key.
+    pub fn or_insert_with_key<F: FnOnce(&K) -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let value = default(&entry.key);
+                entry.insert(value)
+            }
+        }
+    }
+
+    /// Returns a reference to this entry's key.
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Provides in-place mutable access to an occupied entry before any
+    /// potential inserts into the list.
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+}
+
+impl<'a, K, V: Default, C> Entry<'a, K, V, C> {
+    /// Ensures a value is in the entry by inserting the default value if
+    /// empty, and returns a mutable reference to the value in the entry.
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(Default::default)
+    }
+}
+
+impl<'a, K, V, C> VacantEntry<'a, K, V, C> {
+    /// Gets a reference to the key that would be used when inserting a
+    /// value through the `VacantEntry`.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Take ownership of the key.
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Sets the value of the entry with the `VacantEntry`'s key, and
+    /// returns a mutable reference to it.
+    pub fn insert(mut self, value: V) -> &'a mut V {
+        // The pre nodes were recorded by `entry`, no need to search again.
+        let node = self
+            .list
+            ._link(&mut self.pres, &mut self.ranks, self.key, value);
+        unsafe { &mut (*node).value }
+    }
+}
+
+impl<'a, K, V, C> OccupiedEntry<'a, K, V, C> {
+    /// Gets a reference to the key in the entry.
+    pub fn key(&self) -> &K {
+        unsafe { &(*self.node).key }
+    }
+
+    /// Gets a reference to the value in the entry.
+    pub fn get(&self) -> &V {
+        unsafe { &(*self.node).value }
+    }
+
+    /// Gets a mutable reference to the value in the entry.
+    pub fn get_mut(&mut self) -> &mut V {
+        unsafe { &mut (*self.node).value }
+    }
+
+    /// Converts the entry into a mutable reference to its value.
+    pub fn into_mut(self) -> &'a mut V {
+        unsafe { &mut (*self.node).value }
+    }
+
+    /// Sets the value of the entry, and returns the entry's old value.
+    pub fn insert(&mut self, value: V) -> V {
+        core::mem::replace(self.get_mut(), value)
+    }
+
+    /// Takes the value of the entry out of the list, and returns it.
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    /// Take ownership of the key and value from the list.
+    pub fn remove_entry(self) -> (K, V) {
+        self.list._unlink(&self.pres, self.node)
+    }
+}
+
+impl<'a, K: Debug, V: Debug, C> Debug for Entry<'a, K, V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Entry::Vacant(v) => f.debug_tuple("Entry").field(v).finish(),
+            Entry::Occupied(o) => f.debug_tuple("Entry").field(o).finish(),
+        }
+    }
+}
+
+impl<'a, K: Debug, V, C> Debug for VacantEntry<'a, K, V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("VacantEntry").field(self.key()).finish()
+    }
+}
+
+impl<'a, K: Debug, V: Debug, C> Debug for OccupiedEntry<'a, K, V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OccupiedEntry")
+            .field("key", self.key())
+            .field("value", self.get())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec;
+
+    #[test]
+    fn entry() {
+        let mut sk = SkipList::new();
+        *sk.entry("a").or_insert(0) += 1;
+        *sk.entry("a").or_insert(0) += 1;
+        *sk.entry("b").or_insert_with(|| 10) += 1;
+        *sk.entry("c").or_default() += 5;
+        sk.entry("c").and_modify(|v| *v *= 2).or_insert(100);
+        sk.entry("d").and_modify(|v| *v *= 2).or_insert(100);
+        assert_eq!(
+            sk.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(),
+            vec![("a", 2), ("b", 11), ("c", 10), ("d", 100)]
+        );
+        assert_eq!(sk.len(), 4);
+
+        match sk.entry("b") {
+            Entry::Occupied(mut o) => {
+                assert_eq!(o.key(), &"b");
+                assert_eq!(o.insert(12), 11);
+                assert_eq!(o.remove_entry(), ("b", 12));
+            }
+            Entry::Vacant(_) => unreachable!(),
+        }
+        assert_eq!(sk.get("b"), None);
+        assert_eq!(sk.len(), 3);
+
+        match sk.entry("0") {
+            Entry::Vacant(v) => {
+                assert_eq!(v.key(), &"0");
+                *v.insert(1) += 1;
+            }
+            Entry::Occupied(_) => unreachable!(),
+        }
+        assert_eq!(sk.first_key_value(), Some((&"0", &2)));
+    }
+
+    #[test]
+    fn entry_counts() {
+        let mut sk = SkipList::new();
+        for i in 0..2000 {
+            *sk.entry(i % 97).or_insert(0) += 1;
+        }
+        assert_eq!(sk.len(), 97);
+        assert_eq!(sk.iter().map(|(_, v)| v).sum::<i32>(), 2000);
+        assert!(sk.iter().map(|(k, _)| *k).eq(0..97));
+    }
+}
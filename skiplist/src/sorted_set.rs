@@ -0,0 +1,503 @@
+use crate::error::SkipListError;
+use crate::skip::{Range, SkipList};
+use core::iter::FusedIterator;
+use core::ops::{BitOr, Bound, RangeBounds};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Conditions of `SortedSet::zadd`, the flags of the Redis `ZADD` command.
+/// Flags are combined with `|`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ZAddFlags(u8);
+
+impl ZAddFlags {
+    /// Add new members and update existing ones.
+    pub const NONE: ZAddFlags = ZAddFlags(0);
+    /// Only add new members.
+    pub const NX: ZAddFlags = ZAddFlags(1);
+    /// Only update existing members.
+    pub const XX: ZAddFlags = ZAddFlags(1 << 1);
+    /// Only update existing members if the new score is greater.
+    pub const GT: ZAddFlags = ZAddFlags(1 << 2);
+    /// Only update existing members if the new score is less.
+    pub const LT: ZAddFlags = ZAddFlags(1 << 3);
+
+    /// Returns `true` if all flags of `other` are set.
+    pub fn contains(self, other: ZAddFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for ZAddFlags {
+    type Output = ZAddFlags;
+
+    fn bitor(self, other: ZAddFlags) -> ZAddFlags {
+        ZAddFlags(self.0 | other.0)
+    }
+}
+
+// Scores are ordered by `f64::total_cmp`, NaN never gets in.
+#[derive(Clone, Copy, Debug)]
+struct Score(f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Score) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Score) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Score) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+// `Min` and `Max` are before and after every member with the same score,
+// they bound searches by score alone.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Edge<M> {
+    Min,
+    Member(M),
+    Max,
+}
+
+// Members are ordered by score, then by member like Redis.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct ScoreKey<M> {
+    score: Score,
+    member: Edge<M>,
+}
+
+impl<M> ScoreKey<M> {
+    fn new(score: f64, member: M) -> ScoreKey<M> {
+        ScoreKey {
+            score: Score(score),
+            member: Edge::Member(member),
+        }
+    }
+
+    fn edge(score: f64, member: Edge<M>) -> ScoreKey<M> {
+        ScoreKey {
+            score: Score(score),
+            member,
+        }
+    }
+}
+
+/// A set of members ordered by score, following the Redis sorted set.
+///
+/// Scores live in a `HashMap` for `O(1)` lookups by member, and the
+/// members are kept in a `SkipList` ordered by score, then by member, which
+/// gives ranks and ranges in `O(log n)`.
+///
+/// ```
+/// use skiplist::{SortedSet, ZAddFlags};
+///
+/// let mut board = SortedSet::new();
+/// board.zadd("alice", 30.0, ZAddFlags::NONE).unwrap();
+/// board.zadd("bob", 10.0, ZAddFlags::NONE).unwrap();
+/// board.zadd("carol", 20.0, ZAddFlags::NONE).unwrap();
+/// board.zincrby("bob", 15.0).unwrap();
+/// assert_eq!(board.zrevrank(&"bob"), Some(1));
+/// assert!(board.zrange_by_score(21.0..).eq(vec![(&"bob", 25.0), (&"alice", 30.0)]));
+/// ```
+pub struct SortedSet<M> {
+    scores: HashMap<M, f64>,
+    list: SkipList<ScoreKey<M>, ()>,
+}
+
+impl<M> SortedSet<M> {
+    pub fn new() -> SortedSet<M> {
+        SortedSet {
+            scores: HashMap::new(),
+            list: SkipList::new(),
+        }
+    }
+
+    /// Returns the number of members, `ZCARD`.
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    /// Returns `true` if the set has no members.
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+}
+
+impl<M: Hash + Ord + Clone> SortedSet<M> {
+    /// Sets the score of `member`, adding it if it is new, under the
+    /// conditions of `flags`. Returns `true` if the member was added.
+    ///
+    /// Returns `SkipListError::NanScore` if `score` is NaN, and
+    /// `SkipListError::IncompatibleFlags` if `flags` has both `NX` and
+    /// `XX`, or more than one of `NX`, `GT` and `LT`.
+    pub fn zadd(&mut self, member: M, score: f64, flags: ZAddFlags) -> Result<bool, SkipListError> {
+        if score.is_nan() {
+            return Err(SkipListError::NanScore);
+        }
+        let conditions = [ZAddFlags::NX, ZAddFlags::GT, ZAddFlags::LT];
+        if flags.contains(ZAddFlags::NX | ZAddFlags::XX)
+            || conditions.iter().filter(|c| flags.contains(**c)).count() > 1
+        {
+            return Err(SkipListError::IncompatibleFlags);
+        }
+
+        match self.scores.get(&member).copied() {
+            Some(old) => {
+                let skip = flags.contains(ZAddFlags::NX)
+                    || (flags.contains(ZAddFlags::GT) && score <= old)
+                    || (flags.contains(ZAddFlags::LT) && score >= old);
+                if !skip {
+                    self._update(member, old, score);
+                }
+                Ok(false)
+            }
+            None if flags.contains(ZAddFlags::XX) => Ok(false),
+            None => {
+                self._add(member, score);
+                Ok(true)
+            }
+        }
+    }
+
+    /// Adds `increment` to the score of `member`, which starts from 0 if
+    /// it is new. Returns the new score.
+    ///
+    /// Returns `SkipListError::NanScore` if the new score is NaN, the set is
+    /// left untouched.
+    pub fn zincrby(&mut self, member: M, increment: f64) -> Result<f64, SkipListError> {
+        match self.scores.get(&member).copied() {
+            Some(old) => {
+                let score = old + increment;
+                if score.is_nan() {
+                    return Err(SkipListError::NanScore);
+                }
+                self._update(member, old, score);
+                Ok(score)
+            }
+            None => {
+                if increment.is_nan() {
+                    return Err(SkipListError::NanScore);
+                }
+                self._add(member, increment);
+                Ok(increment)
+            }
+        }
+    }
+
+    /// Visits the members from the lowest score to the highest.
+    pub fn iter(&self) -> ZRange<'_, M> {
+        ZRange {
+            range: self.list.range::<ScoreKey<M>, _>(..),
+        }
+    }
+
+    /// Removes `member`, returning whether it was in the set.
+    pub fn zrem(&mut self, member: &M) -> bool {
+        match self.scores.remove(member) {
+            Some(score) => {
+                self.list.remove(&ScoreKey::new(score, member.clone()));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the score of `member`.
+    pub fn zscore(&self, member: &M) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Returns the position of `member` from the lowest score, starting
+    /// from 0.
+    pub fn zrank(&self, member: &M) -> Option<usize> {
+        let score = self.scores.get(member)?;
+        self.list.rank(&ScoreKey::new(*score, member.clone()))
+    }
+
+    /// Returns the position of `member` from the highest score, starting
+    /// from 0.
+    pub fn zrevrank(&self, member: &M) -> Option<usize> {
+        self.zrank(member).map(|rank| self.len() - 1 - rank)
+    }
+
+    /// Visits the members whose scores are inside `range`, from the lowest
+    /// score. An empty range yields nothing instead of panicking.
+    pub fn zrange_by_score<R: RangeBounds<f64>>(&self, range: R) -> ZRange<'_, M> {
+        ZRange {
+            range: self.list.range(self._score_bounds(&range)),
+        }
+    }
+
+    /// Returns the number of members whose scores are inside `range` in
+    /// `O(log n)`.
+    pub fn zcount<R: RangeBounds<f64>>(&self, range: R) -> usize {
+        self.list.range_count(self._score_bounds(&range))
+    }
+
+    /// Visits the members inside `[min, max]` by member order. Like Redis,
+    /// every member must have the same score, otherwise the result is
+    /// unspecified. An empty range yields nothing instead of panicking.
+    pub fn zrange_by_lex(&self, min: Bound<&M>, max: Bound<&M>) -> ZRange<'_, M> {
+        let score = match self.list.first_key_value() {
+            Some((first, _)) => first.score.0,
+            None => 0.0,
+        };
+        let edge = |bound: Bound<&M>, unbounded: Edge<M>| match bound {
+            Bound::Included(m) => Bound::Included(ScoreKey::new(score, m.clone())),
+            Bound::Excluded(m) => Bound::Excluded(ScoreKey::new(score, m.clone())),
+            Bound::Unbounded => Bound::Included(ScoreKey::edge(score, unbounded)),
+        };
+        let (start, end) = (edge(min, Edge::Min), edge(max, Edge::Max));
+        ZRange {
+            range: self.list.range(_non_empty(start, end)),
+        }
+    }
+
+    /// Removes the members whose positions from the lowest score are inside
+    /// `[start, stop]`, returning how many were removed. Negative positions
+    /// count from the highest score, -1 being the last member.
+    pub fn zrem_range_by_rank(&mut self, start: isize, stop: isize) -> usize {
+        let len = self.len() as isize;
+        let start = if start < 0 { (len + start).max(0) } else { start };
+        let stop = if stop < 0 { len + stop } else { stop.min(len - 1) };
+        if start > stop || start >= len {
+            return 0;
+        }
+
+        let key = |rank| self.list.get_by_index(rank as usize).unwrap().0.clone();
+        let (first, last) = (key(start), key(stop));
+        let mut removed = 0;
+        for (key, _) in self.list.drain_range(first..=last) {
+            if let Edge::Member(member) = key.member {
+                self.scores.remove(&member);
+            }
+            removed += 1;
+        }
+        removed
+    }
+
+    fn _add(&mut self, member: M, score: f64) {
+        let score = _normalize(score);
+        self.scores.insert(member.clone(), score);
+        self.list.insert(ScoreKey::new(score, member), ());
+    }
+
+    fn _update(&mut self, member: M, old: f64, score: f64) {
+        self.list.remove(&ScoreKey::new(old, member.clone()));
+        self._add(member, score);
+    }
+
+    fn _score_bounds<R: RangeBounds<f64>>(&self, range: &R) -> (Bound<ScoreKey<M>>, Bound<ScoreKey<M>>) {
+        let start = match range.start_bound() {
+            Bound::Included(s) => Bound::Included(ScoreKey::edge(_normalize(*s), Edge::Min)),
+            Bound::Excluded(s) => Bound::Excluded(ScoreKey::edge(_normalize(*s), Edge::Max)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(e) => Bound::Included(ScoreKey::edge(_normalize(*e), Edge::Max)),
+            Bound::Excluded(e) => Bound::Excluded(ScoreKey::edge(_normalize(*e), Edge::Min)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        _non_empty(start, end)
+    }
+}
+
+// Redis doesn't tell -0 from +0, scores and bounds are stored as +0.
+fn _normalize(score: f64) -> f64 {
+    if score == 0.0 {
+        0.0
+    } else {
+        score
+    }
+}
+
+// Replace a range the list would reject with one that is just empty.
+fn _non_empty<M: Ord>(
+    start: Bound<ScoreKey<M>>,
+    end: Bound<ScoreKey<M>>,
+) -> (Bound<ScoreKey<M>>, Bound<ScoreKey<M>>) {
+    let empty = match (&start, &end) {
+        (Bound::Excluded(s), Bound::Excluded(e)) => s >= e,
+        (Bound::Included(s), Bound::Included(e))
+        | (Bound::Included(s), Bound::Excluded(e))
+        | (Bound::Excluded(s), Bound::Included(e)) => s > e,
+        _ => false,
+    };
+    if empty {
+        let min = || ScoreKey::edge(0.0, Edge::Min);
+        (Bound::Included(min()), Bound::Excluded(min()))
+    } else {
+        (start, end)
+    }
+}
+
+impl<M> Default for SortedSet<M> {
+    fn default() -> SortedSet<M> {
+        SortedSet::new()
+    }
+}
+
+/// An iterator over members and their scores in a `SortedSet`.
+pub struct ZRange<'a, M> {
+    range: Range<'a, ScoreKey<M>, ()>,
+}
+
+impl<'a, M> ZRange<'a, M> {
+    fn _item(key: &'a ScoreKey<M>) -> (&'a M, f64) {
+        match &key.member {
+            Edge::Member(member) => (member, key.score.0),
+            _ => unreachable!("edges are never stored"),
+        }
+    }
+}
+
+impl<'a, M> Iterator for ZRange<'a, M> {
+    type Item = (&'a M, f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.range.next().map(|(k, _)| ZRange::_item(k))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        // Positional skips like `LIMIT offset` take `O(log n)`.
+        self.range.nth(n).map(|(k, _)| ZRange::_item(k))
+    }
+}
+
+impl<'a, M> DoubleEndedIterator for ZRange<'a, M> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.range.next_back().map(|(k, _)| ZRange::_item(k))
+    }
+}
+
+impl<'a, M> ExactSizeIterator for ZRange<'a, M> {}
+
+impl<'a, M> FusedIterator for ZRange<'a, M> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn zadd_flags() {
+        let mut zs = SortedSet::new();
+        assert_eq!(zs.zadd("a", 1.0, ZAddFlags::NONE), Ok(true));
+        assert_eq!(zs.zadd("a", 2.0, ZAddFlags::NONE), Ok(false));
+        assert_eq!(zs.zscore(&"a"), Some(2.0));
+        assert_eq!(zs.zadd("a", 3.0, ZAddFlags::NX), Ok(false));
+        assert_eq!(zs.zadd("b", 3.0, ZAddFlags::XX), Ok(false));
+        assert_eq!(zs.zscore(&"b"), None);
+        assert_eq!(zs.zadd("a", 1.0, ZAddFlags::GT), Ok(false));
+        assert_eq!(zs.zscore(&"a"), Some(2.0));
+        assert_eq!(zs.zadd("a", 5.0, ZAddFlags::GT | ZAddFlags::XX), Ok(false));
+        assert_eq!(zs.zscore(&"a"), Some(5.0));
+        assert_eq!(zs.zadd("a", 4.0, ZAddFlags::LT), Ok(false));
+        assert_eq!(zs.zscore(&"a"), Some(4.0));
+        assert_eq!(zs.zadd("c", 9.0, ZAddFlags::LT), Ok(true));
+        assert_eq!(
+            zs.zadd("a", 1.0, ZAddFlags::NX | ZAddFlags::XX),
+            Err(SkipListError::IncompatibleFlags)
+        );
+        assert_eq!(
+            zs.zadd("a", 1.0, ZAddFlags::GT | ZAddFlags::LT),
+            Err(SkipListError::IncompatibleFlags)
+        );
+        assert_eq!(zs.zadd("a", f64::NAN, ZAddFlags::NONE), Err(SkipListError::NanScore));
+
+        assert_eq!(zs.zincrby("a", 1.5), Ok(5.5));
+        assert_eq!(zs.zincrby("d", -1.0), Ok(-1.0));
+        zs.zadd("e", f64::INFINITY, ZAddFlags::NONE).unwrap();
+        assert_eq!(zs.zincrby("e", f64::NEG_INFINITY), Err(SkipListError::NanScore));
+        assert_eq!(zs.zscore(&"e"), Some(f64::INFINITY));
+        assert!(zs.iter().map(|(m, _)| *m).eq(vec!["d", "a", "c", "e"]));
+        assert!(zs.zrem(&"d"));
+        assert!(!zs.zrem(&"d"));
+        assert_eq!(zs.len(), 3);
+    }
+
+    #[test]
+    fn negative_zero() {
+        let mut zs = SortedSet::new();
+        zs.zadd("a", -0.0, ZAddFlags::NONE).unwrap();
+        zs.zadd("b", 0.0, ZAddFlags::NONE).unwrap();
+        zs.zadd("c", -1.0, ZAddFlags::NONE).unwrap();
+        assert_eq!(zs.zcount(..=-0.0), 3);
+        assert_eq!(zs.zcount(..-0.0), 1);
+        assert_eq!(zs.zcount(0.0..), 2);
+        assert!(zs.zrange_by_score(-0.0..=-0.0).map(|(m, _)| *m).eq(vec!["a", "b"]));
+        assert_eq!(zs.zrange_by_score(-1.0..-0.0).count(), 1);
+    }
+
+    #[test]
+    fn ranks_and_ranges() {
+        let mut rng = StdRng::seed_from_u64(18);
+        let mut zs = SortedSet::new();
+        for i in 0..500u32 {
+            zs.zadd(i, rng.gen_range(0, 50) as f64, ZAddFlags::NONE).unwrap();
+        }
+        for _ in 0..500 {
+            let i = rng.gen_range(0, 500);
+            zs.zincrby(i, rng.gen_range(-5, 5) as f64).unwrap();
+        }
+        let mut sorted: Vec<(f64, u32)> = (0..500).map(|i| (zs.zscore(&i).unwrap(), i)).collect();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        for (rank, (_, i)) in sorted.iter().enumerate() {
+            assert_eq!(zs.zrank(i), Some(rank));
+            assert_eq!(zs.zrevrank(i), Some(499 - rank));
+        }
+
+        let inside = |s: f64| s > 10.0 && s <= 20.0;
+        let expected: Vec<_> = sorted.iter().filter(|(s, _)| inside(*s)).collect();
+        let range = (Bound::Excluded(10.0), Bound::Included(20.0));
+        assert!(zs.zrange_by_score(range).map(|(m, s)| (s, *m)).eq(expected.iter().map(|e| **e)));
+        assert_eq!(zs.zcount(range), expected.len());
+        assert_eq!(zs.zcount(..), 500);
+        assert_eq!(zs.zcount(30.0..10.0), 0);
+        assert_eq!(zs.zrange_by_score(20.0..20.0).count(), 0);
+        let limit: Vec<_> = zs.zrange_by_score(..).skip(10).take(5).map(|(m, _)| *m).collect();
+        assert!(limit.into_iter().eq(sorted[10..15].iter().map(|(_, m)| *m)));
+
+        assert_eq!(zs.zrem_range_by_rank(0, 9), 10);
+        assert_eq!(zs.zrem_range_by_rank(-10, -1), 10);
+        assert_eq!(zs.zrem_range_by_rank(-1000, 4), 5);
+        assert_eq!(zs.zrem_range_by_rank(10, 5), 0);
+        assert_eq!(zs.zrem_range_by_rank(1000, 2000), 0);
+        assert_eq!(zs.len(), 475);
+        assert!(zs.iter().map(|(m, _)| *m).eq(sorted[15..490].iter().map(|(_, m)| *m)));
+        assert_eq!(zs.zscore(&sorted[0].1), None);
+        assert_eq!(zs.zrank(&sorted[15].1), Some(0));
+    }
+
+    #[test]
+    fn lex() {
+        let mut zs = SortedSet::new();
+        for m in &["a", "b", "c", "d", "e", "f", "g"] {
+            zs.zadd(m.to_string(), 0.0, ZAddFlags::NONE).unwrap();
+        }
+        let lex = |min, max| zs.zrange_by_lex(min, max).map(|(m, _)| m.as_str()).collect::<String>();
+        let (aa, b, c, f) = ("aa".to_string(), "b".to_string(), "c".to_string(), "f".to_string());
+        assert_eq!(lex(Bound::Unbounded, Bound::Included(&c)), "abc");
+        assert_eq!(lex(Bound::Excluded(&b), Bound::Excluded(&f)), "cde");
+        assert_eq!(lex(Bound::Included(&aa), Bound::Unbounded), "bcdefg");
+        assert_eq!(lex(Bound::Included(&f), Bound::Included(&b)), "");
+        assert_eq!(lex(Bound::Excluded(&b), Bound::Excluded(&b)), "");
+        assert_eq!(SortedSet::<String>::new().zrange_by_lex(Bound::Unbounded, Bound::Unbounded).count(), 0);
+    }
+}